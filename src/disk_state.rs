@@ -0,0 +1,40 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    fs::Metadata,
+    hash::{Hash, Hasher},
+    time::SystemTime,
+};
+
+// What the file looked like on disk when it was last read or written, to
+// notice when something else changes it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiskState {
+    modified: Option<SystemTime>,
+    len: u64,
+    hash: u64,
+}
+
+impl DiskState {
+    pub fn new(metadata: &Metadata, contents: &str) -> Self {
+        Self {
+            modified: metadata.modified().ok(),
+            len: metadata.len(),
+            hash: hash(contents),
+        }
+    }
+
+    // Cheap check that avoids reading the file when nothing touched it
+    pub fn metadata_matches(&self, metadata: &Metadata) -> bool {
+        self.modified == metadata.modified().ok() && self.len == metadata.len()
+    }
+
+    pub fn contents_match(&self, contents: &str) -> bool {
+        self.hash == hash(contents)
+    }
+}
+
+fn hash(contents: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    contents.hash(&mut hasher);
+    hasher.finish()
+}
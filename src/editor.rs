@@ -0,0 +1,455 @@
+use std::{io, path::PathBuf};
+
+use crossterm::{
+    event::{KeyCode, KeyEvent, KeyModifiers, MouseEvent, MouseEventKind},
+    style::Color,
+};
+
+use crate::{
+    kill_ring::KillRing,
+    layout::{Layout, Rect, SplitKind},
+    message::MessageLevel,
+    screen::{Screen, Style},
+    text_editor::{TextEditor, View},
+    unicode,
+};
+
+#[derive(Debug)]
+pub struct Window {
+    pub buffer: usize,
+    // Only up to date while the window isn't focused, since the focused
+    // window's view lives in its buffer
+    pub view: View,
+}
+
+// Owns every open buffer and the windows showing them, and routes input to
+// the buffer in the focused window
+#[derive(Debug)]
+pub struct Editor {
+    pub alive: bool,
+    pub buffers: Vec<TextEditor>,
+    // Buffer in the focused window
+    pub current: usize,
+    // Cuts can be pasted into any buffer
+    pub kill_ring: KillRing,
+    pub windows: Vec<Window>,
+    pub layout: Layout,
+    pub focused: usize,
+    // Where each window was put on the screen by the last resize
+    pub areas: Vec<(usize, Rect)>,
+    // Name typed so far while switching buffers by name
+    pub switcher: Option<String>,
+}
+
+impl Editor {
+    pub fn open_files(paths: Vec<PathBuf>) -> Result<Self, io::Error> {
+        let buffers = paths
+            .into_iter()
+            .map(TextEditor::open_file)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self {
+            alive: true,
+            buffers,
+            current: 0,
+            kill_ring: KillRing::default(),
+            windows: vec![Window {
+                buffer: 0,
+                view: View::default(),
+            }],
+            layout: Layout::Window(0),
+            focused: 0,
+            areas: Vec::new(),
+            switcher: None,
+        })
+    }
+
+    pub fn current(&mut self) -> &mut TextEditor {
+        &mut self.buffers[self.current]
+    }
+
+    pub fn handle_key(&mut self, event: KeyEvent) {
+        if self.switcher.is_some() {
+            self.handle_switcher_key(event);
+            return;
+        }
+
+        // Buffer commands wait until any prompt in the buffer is done with
+        let prompting = self.buffers[self.current].is_prompting();
+        let count = self.buffers.len();
+
+        match event.code {
+            // Quit without saving any buffer, keeping swap files that are
+            // still waiting to be recovered
+            KeyCode::Char('q') if event.modifiers.contains(KeyModifiers::CONTROL) => {
+                for buffer in &mut self.buffers {
+                    if buffer.recovery.is_none() {
+                        buffer.swap.remove();
+                    }
+                }
+                self.alive = false;
+            }
+
+            // Next & previous buffer
+            KeyCode::Char('.') if event.modifiers.contains(KeyModifiers::ALT) && !prompting => {
+                self.switch_to((self.current + 1) % count)
+            }
+            KeyCode::Char(',') if event.modifiers.contains(KeyModifiers::ALT) && !prompting => {
+                self.switch_to((self.current + count - 1) % count)
+            }
+
+            // Buffer by name
+            KeyCode::Char('b') if event.modifiers.contains(KeyModifiers::CONTROL) && !prompting => {
+                self.switcher = Some(String::new())
+            }
+
+            // Split, cycle through & close windows
+            KeyCode::Char('2') if event.modifiers.contains(KeyModifiers::ALT) && !prompting => {
+                self.split(SplitKind::Horizontal)
+            }
+            KeyCode::Char('3') if event.modifiers.contains(KeyModifiers::ALT) && !prompting => {
+                self.split(SplitKind::Vertical)
+            }
+            KeyCode::Char('o') if event.modifiers.contains(KeyModifiers::ALT) && !prompting => {
+                let order = self.layout.windows();
+                let position = order
+                    .iter()
+                    .position(|&window| window == self.focused)
+                    .unwrap_or(0);
+                self.focus(order[(position + 1) % order.len()]);
+            }
+            KeyCode::Char('0') if event.modifiers.contains(KeyModifiers::ALT) && !prompting => {
+                self.close_window()
+            }
+            KeyCode::Char('1') if event.modifiers.contains(KeyModifiers::ALT) && !prompting => {
+                let window = self.windows.swap_remove(self.focused);
+                self.windows = vec![window];
+                self.layout = Layout::Window(0);
+                self.focused = 0;
+            }
+
+            _ => {
+                self.with_other_views(|buffer, kill_ring| buffer.handle_key(event, kill_ring));
+
+                // Quitting from a saved buffer only closes its window while
+                // there are others
+                if !self.current().alive {
+                    if self.windows.len() > 1 {
+                        self.current().alive = true;
+                        self.close_window();
+                    } else {
+                        self.quit();
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn handle_paste(&mut self, text: String) {
+        match &mut self.switcher {
+            Some(input) => input.push_str(text.lines().next().unwrap_or_default()),
+            None => self.with_other_views(|buffer, _| buffer.handle_paste(text)),
+        }
+    }
+
+    // Clicking or scrolling a window focuses it first, while dragging stays
+    // in the window it started in
+    pub fn handle_mouse(&mut self, event: MouseEvent) {
+        if self.switcher.is_some() {
+            return;
+        }
+
+        if !matches!(event.kind, MouseEventKind::Drag(_)) && !self.current().is_prompting() {
+            match self
+                .areas
+                .iter()
+                .find(|(_, area)| area.contains(event.column, event.row))
+            {
+                Some(&(window, _)) => self.focus(window),
+                None => return,
+            }
+        }
+
+        self.current().handle_mouse(event);
+    }
+
+    pub fn tick(&mut self) {
+        for buffer in &mut self.buffers {
+            buffer.tick();
+        }
+    }
+
+    // Lays the windows out and fits the focused one to its area. The others
+    // are fitted when they are drawn
+    pub fn resize(&mut self, width: u16, height: u16) {
+        self.areas = self.layout.areas(Rect {
+            x: 0,
+            y: 0,
+            width,
+            height,
+        });
+
+        let area = self.area(self.focused);
+        self.current().resize(area);
+    }
+
+    // Text cut or copied in any buffer since the last call, for the
+    // system clipboard
+    pub fn take_clipboard(&mut self) -> Option<String> {
+        self.kill_ring.take_pending()
+    }
+
+    pub fn render(&mut self, screen: &mut Screen) {
+        for &(window, area) in &self.areas {
+            let buffer = &mut self.buffers[self.windows[window].buffer];
+            screen.set_clip(Some(area.x + area.width));
+
+            if window == self.focused {
+                buffer.render(screen, true);
+            } else {
+                // Draw with the window's view in place of the buffer's own
+                let view = buffer.set_view(std::mem::take(&mut self.windows[window].view));
+                buffer.resize(area);
+                buffer.render(screen, false);
+                self.windows[window].view = buffer.set_view(view);
+            }
+
+            screen.set_clip(None);
+
+            // Separator to the right of windows split vertically
+            if area.x + area.width < screen.width() {
+                for y in area.y..area.y + area.height {
+                    screen.put_str(area.x + area.width, y, "│", Style::fg(Color::DarkGrey));
+                }
+            }
+        }
+
+        if let Some(input) = &self.switcher {
+            self.render_switcher(screen, input);
+        }
+    }
+
+    // The current buffer only quits when it's saved, after which this checks
+    // that no other buffer still has unsaved changes
+    fn quit(&mut self) {
+        self.current().alive = true;
+
+        let unsaved = self.buffers.iter().filter(|buffer| !buffer.saved).count();
+
+        match self.buffers.iter().position(|buffer| !buffer.saved) {
+            Some(index) => {
+                self.switch_to(index);
+                self.current().set_message(
+                    MessageLevel::Warning,
+                    format!(
+                        "Unsaved changes in {} buffer{}! Save with Ctrl+S or discard them all with Ctrl+Q",
+                        unsaved,
+                        if unsaved == 1 { "" } else { "s" }
+                    ),
+                );
+            }
+            None => self.alive = false,
+        }
+    }
+
+    // Shows another buffer in the focused window
+    fn switch_to(&mut self, index: usize) {
+        if index == self.current {
+            return;
+        }
+
+        self.set_current(index);
+        self.windows[self.focused].buffer = index;
+
+        let text = format!(
+            "{} ({}/{})",
+            self.buffers[index].path.to_string_lossy(),
+            index + 1,
+            self.buffers.len()
+        );
+        self.current().set_message(MessageLevel::Info, text);
+    }
+
+    // Lends the views of the other windows on the current buffer to it while
+    // it handles input, so that its edits keep them on the same text
+    fn with_other_views(&mut self, handle: impl FnOnce(&mut TextEditor, &mut KillRing)) {
+        let others: Vec<usize> = (0..self.windows.len())
+            .filter(|&window| window != self.focused && self.windows[window].buffer == self.current)
+            .collect();
+
+        let buffer = &mut self.buffers[self.current];
+        buffer.other_views = others
+            .iter()
+            .map(|&window| std::mem::take(&mut self.windows[window].view))
+            .collect();
+
+        handle(buffer, &mut self.kill_ring);
+
+        for (window, view) in others.into_iter().zip(buffer.other_views.drain(..)) {
+            self.windows[window].view = view;
+        }
+    }
+
+    fn set_current(&mut self, index: usize) {
+        // A yank or run of kills can't carry on in another buffer
+        if index != self.current {
+            self.kill_ring.yanked = None;
+            self.kill_ring.killing = false;
+        }
+
+        self.current = index;
+    }
+
+    fn area(&self, window: usize) -> Rect {
+        self.areas
+            .iter()
+            .find(|(id, _)| *id == window)
+            .map_or(Rect::default(), |(_, area)| *area)
+    }
+
+    // Splits the focused window in two, both showing the same part of its
+    // buffer. Focus stays in the top or left half
+    fn split(&mut self, kind: SplitKind) {
+        let area = self.area(self.focused);
+        let too_small = match kind {
+            SplitKind::Horizontal => area.height < 4,
+            SplitKind::Vertical => area.width < 20,
+        };
+
+        if too_small {
+            self.current()
+                .set_message(MessageLevel::Warning, "The window is too small to split");
+            return;
+        }
+
+        let window = Window {
+            buffer: self.current,
+            view: self.current().view.clone(),
+        };
+        self.windows.push(window);
+        self.layout
+            .split(self.focused, self.windows.len() - 1, kind);
+    }
+
+    fn focus(&mut self, window: usize) {
+        if window == self.focused {
+            return;
+        }
+
+        self.windows[self.focused].view = self.current().view.clone();
+        self.load_window(window);
+    }
+
+    // Puts the window's view into its buffer, for a window that's about to
+    // be focused
+    fn load_window(&mut self, window: usize) {
+        self.focused = window;
+
+        let buffer = self.windows[window].buffer;
+        self.set_current(buffer);
+        self.buffers[buffer].set_view(self.windows[window].view.clone());
+    }
+
+    // Closes the focused window, focusing the one after it
+    fn close_window(&mut self) {
+        if self.windows.len() == 1 {
+            self.current()
+                .set_message(MessageLevel::Warning, "This is the only window");
+            return;
+        }
+
+        let position = self
+            .layout
+            .windows()
+            .iter()
+            .position(|&window| window == self.focused)
+            .unwrap_or(0);
+
+        self.windows.remove(self.focused);
+        self.layout.remove(self.focused);
+
+        let order = self.layout.windows();
+        self.load_window(order[position.min(order.len() - 1)]);
+    }
+
+    fn handle_switcher_key(&mut self, event: KeyEvent) {
+        let Some(input) = &mut self.switcher else {
+            return;
+        };
+
+        match event.code {
+            KeyCode::Esc => self.switcher = None,
+            KeyCode::Enter => {
+                let name = self.switcher.take().unwrap_or_default();
+
+                match self.matching_buffers(&name).first() {
+                    Some(&index) => self.switch_to(index),
+                    None => self.current().set_message(
+                        MessageLevel::Warning,
+                        format!("No buffer matches \"{}\"", name),
+                    ),
+                }
+            }
+            KeyCode::Backspace => {
+                input.pop();
+            }
+            KeyCode::Char(c)
+                if !event
+                    .modifiers
+                    .intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) =>
+            {
+                input.push(c);
+            }
+            _ => {}
+        }
+    }
+
+    // Buffers whose path contains `name`, with exact file name matches first
+    fn matching_buffers(&self, name: &str) -> Vec<usize> {
+        let mut matches: Vec<usize> = (0..self.buffers.len())
+            .filter(|&index| self.buffers[index].path.to_string_lossy().contains(name))
+            .collect();
+
+        matches.sort_by_key(|&index| {
+            self.buffers[index]
+                .path
+                .file_name()
+                .is_none_or(|file_name| file_name.to_string_lossy() != name)
+        });
+
+        matches
+    }
+
+    // Drawn over the focused window's toolbar
+    fn render_switcher(&self, screen: &mut Screen, input: &str) {
+        let area = self.area(self.focused);
+        let row = (area.y + area.height).saturating_sub(1);
+        let style = Style {
+            fg: Color::Black,
+            bg: Color::White,
+            ..Style::default()
+        };
+
+        let names = self
+            .matching_buffers(input)
+            .iter()
+            .map(|&index| self.buffers[index].path.to_string_lossy().to_string())
+            .collect::<Vec<_>>()
+            .join("  ");
+
+        screen.set_clip(Some(area.x + area.width));
+        screen.fill(area.x, row, area.width, style);
+        screen.put_str(
+            area.x + area.width.saturating_sub(unicode::width(&names) as u16 + 1),
+            row,
+            &names,
+            style,
+        );
+
+        let prompt_end = screen.put_str(area.x, row, "Switch to buffer: ", style);
+        let input_end = screen.put_str(prompt_end, row, input, style);
+        screen.put_str(input_end, row, " ", style);
+        screen.set_cursor(Some((input_end, row)));
+        screen.set_clip(None);
+    }
+}
@@ -0,0 +1,166 @@
+const BOM: char = '\u{FEFF}';
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineEnding {
+    Lf,
+    CrLf,
+}
+
+impl LineEnding {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::CrLf => "\r\n",
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            LineEnding::Lf => "LF",
+            LineEnding::CrLf => "CRLF",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileFormat {
+    pub line_ending: LineEnding,
+    pub final_newline: bool,
+    pub bom: bool,
+}
+
+impl Default for FileFormat {
+    fn default() -> Self {
+        Self {
+            line_ending: LineEnding::Lf,
+            final_newline: true,
+            bom: false,
+        }
+    }
+}
+
+impl FileFormat {
+    // Detects the format of the file contents and returns them normalized to
+    // LF line breaks without a BOM or final newline
+    pub fn detect(contents: &str) -> (Self, String) {
+        let bom = contents.starts_with(BOM);
+        let contents = contents.strip_prefix(BOM).unwrap_or(contents);
+
+        let line_breaks = contents.matches('\n').count();
+        let crlf_line_breaks = contents.matches("\r\n").count();
+
+        // Mixed files are left as they are so that saving doesn't rewrite
+        // their line breaks
+        let (line_ending, mut text) = if line_breaks > 0 && crlf_line_breaks == line_breaks {
+            (LineEnding::CrLf, contents.replace("\r\n", "\n"))
+        } else {
+            (LineEnding::Lf, contents.to_string())
+        };
+
+        let final_newline = text.ends_with('\n');
+        if final_newline {
+            text.pop();
+        }
+
+        (
+            Self {
+                line_ending,
+                final_newline,
+                bom,
+            },
+            text,
+        )
+    }
+
+    pub fn encode(&self, text: &str) -> String {
+        let mut contents = String::with_capacity(text.len() + 4);
+
+        if self.bom {
+            contents.push(BOM);
+        }
+
+        match self.line_ending {
+            LineEnding::Lf => contents.push_str(text),
+            LineEnding::CrLf => contents.push_str(&text.replace('\n', "\r\n")),
+        }
+
+        if self.final_newline {
+            contents.push_str(self.line_ending.as_str());
+        }
+
+        contents
+    }
+
+    pub fn describe(&self) -> String {
+        let encoding = if self.bom { "UTF-8 BOM" } else { "UTF-8" };
+        let final_newline = if self.final_newline { "" } else { " noeol" };
+
+        format!("{} {}{}", encoding, self.line_ending.name(), final_newline)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(contents: &str) -> (FileFormat, String) {
+        let (format, text) = FileFormat::detect(contents);
+        assert_eq!(format.encode(&text), contents);
+        (format, text)
+    }
+
+    #[test]
+    fn lf() {
+        let (format, text) = round_trip("one\ntwo\n");
+
+        assert_eq!(format, FileFormat::default());
+        assert_eq!(text, "one\ntwo");
+    }
+
+    #[test]
+    fn crlf() {
+        let (format, text) = round_trip("one\r\ntwo\r\n");
+
+        assert_eq!(format.line_ending, LineEnding::CrLf);
+        assert!(format.final_newline);
+        assert_eq!(text, "one\ntwo");
+    }
+
+    #[test]
+    fn mixed() {
+        let (format, text) = round_trip("one\r\ntwo\r\nthree\n");
+
+        assert_eq!(format.line_ending, LineEnding::Lf);
+        assert_eq!(text, "one\r\ntwo\r\nthree");
+
+        let (format, text) = round_trip("one\ntwo\r\n");
+
+        assert_eq!(format.line_ending, LineEnding::Lf);
+        assert!(format.final_newline);
+        assert_eq!(text, "one\ntwo\r");
+    }
+
+    #[test]
+    fn no_final_newline() {
+        let (format, text) = round_trip("one\r\ntwo");
+
+        assert_eq!(format.line_ending, LineEnding::CrLf);
+        assert!(!format.final_newline);
+        assert_eq!(text, "one\ntwo");
+
+        let (format, text) = round_trip("");
+
+        assert!(!format.final_newline);
+        assert_eq!(text, "");
+    }
+
+    #[test]
+    fn bom() {
+        let (format, text) = round_trip("\u{FEFF}one\r\n");
+
+        assert!(format.bom);
+        assert_eq!(format.line_ending, LineEnding::CrLf);
+        assert_eq!(text, "one");
+        assert_eq!(format.describe(), "UTF-8 BOM CRLF");
+    }
+}
@@ -0,0 +1,131 @@
+use std::ops::Range;
+
+// Ranges to replace and the text to replace them with, in order, to undo or
+// redo a step, along with where the cursor goes afterwards
+pub type Replacements = (Vec<(Range<usize>, String)>, usize);
+
+#[derive(Debug, Clone)]
+pub struct Edit {
+    pub offset: usize,
+    pub deleted: String,
+    pub inserted: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EditKind {
+    Typing,
+    Other,
+}
+
+#[derive(Debug)]
+struct Transaction {
+    edits: Vec<Edit>,
+    kind: EditKind,
+    cursor_before: usize,
+    cursor_after: usize,
+}
+
+#[derive(Debug, Default)]
+pub struct History {
+    undo_stack: Vec<Transaction>,
+    redo_stack: Vec<Transaction>,
+    sealed: bool,
+    grouping: bool,
+}
+
+impl History {
+    pub fn record(
+        &mut self,
+        edit: Edit,
+        kind: EditKind,
+        cursor_before: usize,
+        cursor_after: usize,
+    ) {
+        self.redo_stack.clear();
+
+        if let Some(last) = self.undo_stack.last_mut() {
+            if !self.sealed && (self.grouping || Self::continues_typing(last, &edit, kind)) {
+                last.edits.push(edit);
+                last.cursor_after = cursor_after;
+                return;
+            }
+        }
+
+        self.undo_stack.push(Transaction {
+            edits: vec![edit],
+            kind,
+            cursor_before,
+            cursor_after,
+        });
+        self.sealed = false;
+    }
+
+    // Stops the next edit from being merged into the current undo step
+    pub fn seal(&mut self) {
+        self.sealed = true;
+    }
+
+    // Records every edit until `end_group` as a single undo step
+    pub fn begin_group(&mut self) {
+        self.grouping = true;
+        self.sealed = true;
+    }
+
+    pub fn end_group(&mut self) {
+        self.grouping = false;
+        self.sealed = true;
+    }
+
+    pub fn undo(&mut self) -> Option<Replacements> {
+        let transaction = self.undo_stack.pop()?;
+
+        let replacements = transaction
+            .edits
+            .iter()
+            .rev()
+            .map(|edit| {
+                (
+                    edit.offset..edit.offset + edit.inserted.len(),
+                    edit.deleted.clone(),
+                )
+            })
+            .collect();
+
+        let cursor = transaction.cursor_before;
+        self.redo_stack.push(transaction);
+        self.sealed = true;
+
+        Some((replacements, cursor))
+    }
+
+    pub fn redo(&mut self) -> Option<Replacements> {
+        let transaction = self.redo_stack.pop()?;
+
+        let replacements = transaction
+            .edits
+            .iter()
+            .map(|edit| {
+                (
+                    edit.offset..edit.offset + edit.deleted.len(),
+                    edit.inserted.clone(),
+                )
+            })
+            .collect();
+
+        let cursor = transaction.cursor_after;
+        self.undo_stack.push(transaction);
+        self.sealed = true;
+
+        Some((replacements, cursor))
+    }
+
+    fn continues_typing(last: &Transaction, edit: &Edit, kind: EditKind) -> bool {
+        if kind != EditKind::Typing || last.kind != EditKind::Typing || !edit.deleted.is_empty() {
+            return false;
+        }
+
+        last.edits
+            .last()
+            .is_some_and(|previous| previous.offset + previous.inserted.len() == edit.offset)
+    }
+}
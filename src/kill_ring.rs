@@ -0,0 +1,57 @@
+use std::ops::Range;
+
+const CAPACITY: usize = 32;
+
+#[derive(Debug, Default)]
+pub struct KillRing {
+    entries: Vec<String>,
+    index: usize,
+    // Text waiting to be sent to the system clipboard
+    pending: Option<String>,
+    // What the last command yanked or whether it killed, so that Alt+Y and
+    // repeated kills can build on it
+    pub yanked: Option<Range<usize>>,
+    pub killing: bool,
+}
+
+impl KillRing {
+    pub fn push(&mut self, text: String) {
+        if self.entries.len() == CAPACITY {
+            self.entries.remove(0);
+        }
+
+        self.pending = Some(text.clone());
+        self.entries.push(text);
+        self.index = self.entries.len() - 1;
+    }
+
+    // Extends the newest entry, so that consecutive kills yank back together
+    pub fn append(&mut self, text: &str) {
+        let Some(last) = self.entries.last_mut() else {
+            self.push(text.to_string());
+            return;
+        };
+
+        last.push_str(text);
+        self.pending = Some(last.clone());
+        self.index = self.entries.len() - 1;
+    }
+
+    pub fn current(&self) -> Option<&str> {
+        self.entries.get(self.index).map(String::as_str)
+    }
+
+    // Moves to the next older entry, wrapping around to the newest
+    pub fn rotate(&mut self) -> Option<&str> {
+        if self.entries.is_empty() {
+            return None;
+        }
+
+        self.index = self.index.checked_sub(1).unwrap_or(self.entries.len() - 1);
+        self.current()
+    }
+
+    pub fn take_pending(&mut self) -> Option<String> {
+        self.pending.take()
+    }
+}
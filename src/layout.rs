@@ -0,0 +1,146 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Rect {
+    pub x: u16,
+    pub y: u16,
+    pub width: u16,
+    pub height: u16,
+}
+
+impl Rect {
+    pub fn contains(&self, x: u16, y: u16) -> bool {
+        (self.x..self.x + self.width).contains(&x) && (self.y..self.y + self.height).contains(&y)
+    }
+}
+
+// Horizontal splits stack the two windows, vertical splits put them side by
+// side
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SplitKind {
+    Horizontal,
+    Vertical,
+}
+
+// Windows are numbered by their index in the editor's list of windows, and
+// the screen is split between them in halves
+#[derive(Debug, PartialEq, Eq)]
+pub enum Layout {
+    Window(usize),
+    Split {
+        kind: SplitKind,
+        first: Box<Layout>,
+        second: Box<Layout>,
+    },
+}
+
+impl Layout {
+    // Splits `window` in two, with `new_window` taking the bottom or right half
+    pub fn split(&mut self, window: usize, new_window: usize, kind: SplitKind) {
+        match self {
+            Layout::Window(id) if *id == window => {
+                *self = Layout::Split {
+                    kind,
+                    first: Box::new(Layout::Window(window)),
+                    second: Box::new(Layout::Window(new_window)),
+                };
+            }
+            Layout::Window(_) => {}
+            Layout::Split { first, second, .. } => {
+                first.split(window, new_window, kind);
+                second.split(window, new_window, kind);
+            }
+        }
+    }
+
+    // Removes `window`, giving its space to the other half of its split. The
+    // windows after it are renumbered to match the editor's list
+    pub fn remove(&mut self, window: usize) {
+        self.detach(window);
+        self.renumber(window);
+    }
+
+    // Windows from top left to bottom right
+    pub fn windows(&self) -> Vec<usize> {
+        match self {
+            Layout::Window(window) => vec![*window],
+            Layout::Split { first, second, .. } => {
+                let mut windows = first.windows();
+                windows.extend(second.windows());
+                windows
+            }
+        }
+    }
+
+    // Where each window goes on the screen. Vertical splits leave a column
+    // between the two halves for a separator
+    pub fn areas(&self, area: Rect) -> Vec<(usize, Rect)> {
+        match self {
+            Layout::Window(window) => vec![(*window, area)],
+            Layout::Split {
+                kind,
+                first,
+                second,
+            } => {
+                let (first_area, second_area) = match kind {
+                    SplitKind::Horizontal => {
+                        let height = area.height / 2;
+                        (
+                            Rect { height, ..area },
+                            Rect {
+                                y: area.y + height,
+                                height: area.height - height,
+                                ..area
+                            },
+                        )
+                    }
+                    SplitKind::Vertical => {
+                        let width = area.width.saturating_sub(1) / 2;
+                        (
+                            Rect { width, ..area },
+                            Rect {
+                                x: area.x + width + 1,
+                                width: area.width.saturating_sub(width + 1),
+                                ..area
+                            },
+                        )
+                    }
+                };
+
+                let mut areas = first.areas(first_area);
+                areas.extend(second.areas(second_area));
+                areas
+            }
+        }
+    }
+
+    fn detach(&mut self, window: usize) {
+        let Layout::Split { first, second, .. } = self else {
+            return;
+        };
+
+        let sibling = if **first == Layout::Window(window) {
+            std::mem::replace(&mut **second, Layout::Window(0))
+        } else if **second == Layout::Window(window) {
+            std::mem::replace(&mut **first, Layout::Window(0))
+        } else {
+            first.detach(window);
+            second.detach(window);
+            return;
+        };
+
+        *self = sibling;
+    }
+
+    fn renumber(&mut self, removed: usize) {
+        match self {
+            Layout::Window(id) => {
+                if *id > removed {
+                    *id -= 1;
+                }
+            }
+            Layout::Split { first, second, .. } => {
+                first.renumber(removed);
+                second.renumber(removed);
+            }
+        }
+    }
+}
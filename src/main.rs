@@ -1,49 +1,145 @@
+mod disk_state;
+mod editor;
+mod file_format;
+mod history;
+mod kill_ring;
+mod layout;
+mod message;
+mod motion;
+mod regex;
+mod replace;
+mod screen;
+mod search;
+mod swap;
+mod text_buffer;
 mod text_editor;
+mod unicode;
+mod wrap;
 
-use std::{io::stdout, path::PathBuf};
+use std::{
+    io::{stdout, BufWriter},
+    panic,
+    path::PathBuf,
+    time::Duration,
+};
 
-use crate::text_editor::TextEditor;
+use crate::{editor::Editor, screen::Screen};
 use clap::Parser;
-use crossterm::{
-    cursor, event, execute,
-    terminal::{self, Clear, ClearType},
-    Result,
-};
+use crossterm::{cursor, event, execute, terminal, Result};
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Args {
-    path: PathBuf,
+    #[arg(required = true)]
+    paths: Vec<PathBuf>,
+
+    /// Copy to the system clipboard with OSC 52 escape sequences
+    #[arg(long)]
+    osc52: bool,
+
+    /// Number of columns between tab stops
+    #[arg(long, default_value_t = 4, value_parser = clap::value_parser!(u8).range(1..))]
+    tab_width: u8,
+
+    /// Indent with spaces instead of tabs
+    #[arg(long)]
+    expand_tab: bool,
+
+    /// Place the cursor, select and scroll with the mouse
+    #[arg(long)]
+    mouse: bool,
+
+    /// Wrap long lines onto multiple rows
+    #[arg(long)]
+    soft_wrap: bool,
+}
+
+// Puts the terminal back the way it was, however the editor exits
+struct TerminalGuard;
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        restore_terminal();
+    }
+}
+
+// Errors are ignored since this also runs while panicking, and undoing
+// something that was never set up is harmless
+fn restore_terminal() {
+    let _ = execute!(
+        stdout(),
+        event::DisableMouseCapture,
+        event::DisableBracketedPaste,
+        terminal::LeaveAlternateScreen,
+        cursor::Show
+    );
+    let _ = terminal::disable_raw_mode();
 }
 
 fn main() -> Result<()> {
     let args = Args::parse();
 
-    let mut out = stdout();
+    // Restore the terminal before the panic message is printed, otherwise it
+    // ends up on the alternate screen in raw mode
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        restore_terminal();
+        default_hook(info);
+    }));
+
+    // Declared first so that it drops last, after anything left in `out` is
+    // flushed to the alternate screen
+    let _guard = TerminalGuard;
+    let mut out = BufWriter::new(stdout());
     terminal::enable_raw_mode()?;
+    execute!(
+        out,
+        terminal::EnterAlternateScreen,
+        event::EnableBracketedPaste
+    )?;
+    if args.mouse {
+        execute!(out, event::EnableMouseCapture)?;
+    }
 
-    let mut text_editor = TextEditor::open_file(args.path.clone())?;
+    let mut editor = Editor::open_files(args.paths.clone())?;
+    for buffer in &mut editor.buffers {
+        buffer.tab_width = args.tab_width as usize;
+        buffer.expand_tab = args.expand_tab;
+        buffer.soft_wrap = args.soft_wrap;
+    }
 
-    while text_editor.alive {
-        execute!(out, Clear(ClearType::All))?;
+    let (width, height) = terminal::size()?;
+    let mut screen = Screen::new(width, height);
 
-        text_editor.render(&mut out)?;
+    while editor.alive {
+        // The gutter can change width as lines are added, so the viewport is
+        // recomputed every frame rather than only on resize
+        editor.resize(screen.width(), screen.height());
 
-        execute!(
-            out,
-            cursor::MoveTo(
-                text_editor.cursor_col.try_into().unwrap_or(u16::MAX)
-                    + text_editor.cursor_col_offset,
-                text_editor.cursor_row.try_into().unwrap_or(u16::MAX)
-            ),
-        )?;
+        screen.clear();
+        editor.render(&mut screen);
 
-        match event::read()? {
-            event::Event::Key(event) => text_editor.handle_key(event),
-            _ => {}
+        if let Some(text) = editor.take_clipboard() {
+            if args.osc52 {
+                screen.set_clipboard(&text);
+            }
         }
+
+        screen.flush(&mut out)?;
+
+        // Wake up periodically so that timed state like messages can expire
+        if event::poll(Duration::from_millis(250))? {
+            match event::read()? {
+                event::Event::Key(event) => editor.handle_key(event),
+                event::Event::Paste(text) => editor.handle_paste(text),
+                event::Event::Mouse(event) => editor.handle_mouse(event),
+                event::Event::Resize(width, height) => screen.resize(width, height),
+                _ => {}
+            }
+        }
+
+        editor.tick();
     }
 
-    terminal::disable_raw_mode()?;
     Ok(())
 }
@@ -0,0 +1,63 @@
+use std::time::{Duration, Instant};
+
+use crossterm::style::Color;
+
+use crate::screen::Style;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageLevel {
+    Info,
+    Warning,
+    Error,
+}
+
+impl MessageLevel {
+    fn timeout(&self) -> Duration {
+        match self {
+            MessageLevel::Info => Duration::from_secs(3),
+            MessageLevel::Warning => Duration::from_secs(5),
+            MessageLevel::Error => Duration::from_secs(10),
+        }
+    }
+
+    pub fn style(&self) -> Style {
+        match self {
+            MessageLevel::Info => Style {
+                fg: Color::Black,
+                bg: Color::White,
+                ..Style::default()
+            },
+            MessageLevel::Warning => Style {
+                fg: Color::Black,
+                bg: Color::Yellow,
+                ..Style::default()
+            },
+            MessageLevel::Error => Style {
+                fg: Color::White,
+                bg: Color::Red,
+                ..Style::default()
+            },
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Message {
+    pub level: MessageLevel,
+    pub text: String,
+    expires: Instant,
+}
+
+impl Message {
+    pub fn new(level: MessageLevel, text: String) -> Self {
+        Self {
+            level,
+            text,
+            expires: Instant::now() + level.timeout(),
+        }
+    }
+
+    pub fn is_expired(&self) -> bool {
+        Instant::now() >= self.expires
+    }
+}
@@ -0,0 +1,138 @@
+use std::ops::Range;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CharClass {
+    Whitespace,
+    Word,
+    Punctuation,
+}
+
+impl CharClass {
+    pub fn of(c: char) -> Self {
+        if c.is_whitespace() {
+            CharClass::Whitespace
+        } else if c.is_alphanumeric() || c == '_' {
+            CharClass::Word
+        } else {
+            CharClass::Punctuation
+        }
+    }
+}
+
+// Skips any whitespace after `offset` and then the run of word or punctuation
+// characters that follows it
+pub fn next_word_end(text: &str, offset: usize) -> usize {
+    let mut chars = text[offset..].char_indices().peekable();
+    let mut class = CharClass::Whitespace;
+
+    while let Some(&(_, c)) = chars.peek() {
+        if CharClass::of(c) != CharClass::Whitespace {
+            class = CharClass::of(c);
+            break;
+        }
+        chars.next();
+    }
+
+    while let Some(&(_, c)) = chars.peek() {
+        if CharClass::of(c) != class {
+            break;
+        }
+        chars.next();
+    }
+
+    chars.peek().map_or(text.len(), |&(i, _)| offset + i)
+}
+
+// Mirror of `next_word_end`, moving back to the start of the previous word
+pub fn previous_word_start(text: &str, offset: usize) -> usize {
+    let mut chars = text[..offset].char_indices().rev().peekable();
+    let mut class = CharClass::Whitespace;
+    let mut start = offset;
+
+    while let Some(&(i, c)) = chars.peek() {
+        if CharClass::of(c) != CharClass::Whitespace {
+            class = CharClass::of(c);
+            break;
+        }
+        start = i;
+        chars.next();
+    }
+
+    while let Some(&(i, c)) = chars.peek() {
+        if CharClass::of(c) != class {
+            break;
+        }
+        start = i;
+        chars.next();
+    }
+
+    start
+}
+
+// The run of characters of the same class around `offset`, such as the word
+// under the cursor
+pub fn word_at(text: &str, offset: usize) -> Range<usize> {
+    let Some(c) = text[offset..]
+        .chars()
+        .next()
+        .or_else(|| text[..offset].chars().next_back())
+    else {
+        return offset..offset;
+    };
+    let class = CharClass::of(c);
+
+    let start = text[..offset]
+        .char_indices()
+        .rev()
+        .take_while(|&(_, c)| CharClass::of(c) == class)
+        .last()
+        .map_or(offset, |(i, _)| i);
+    let end = text[offset..]
+        .char_indices()
+        .find(|&(_, c)| CharClass::of(c) != class)
+        .map_or(text.len(), |(i, _)| offset + i);
+
+    start..end
+}
+
+// Offset of the bracket matching the one at `offset`, if there is one
+pub fn matching_bracket(text: &str, offset: usize) -> Option<usize> {
+    let c = text[offset..].chars().next()?;
+    let (open, close, forward) = match c {
+        '(' => ('(', ')', true),
+        '[' => ('[', ']', true),
+        '{' => ('{', '}', true),
+        ')' => ('(', ')', false),
+        ']' => ('[', ']', false),
+        '}' => ('{', '}', false),
+        _ => return None,
+    };
+
+    let mut depth = 0usize;
+
+    if forward {
+        for (i, c) in text[offset..].char_indices() {
+            if c == open {
+                depth += 1;
+            } else if c == close {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(offset + i);
+                }
+            }
+        }
+    } else {
+        for (i, c) in text[..offset + c.len_utf8()].char_indices().rev() {
+            if c == close {
+                depth += 1;
+            } else if c == open {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+        }
+    }
+
+    None
+}
@@ -0,0 +1,751 @@
+use std::{cell::Cell, fmt, ops::Range};
+
+// How deep the matcher may recurse before giving up on a match, which keeps
+// patterns like `(ab)*` on very long lines from overflowing the stack
+const MAX_DEPTH: usize = 2000;
+
+// A small backtracking regular expression engine supporting literals, `.`,
+// character classes, `\d \w \s \b` and their negations, anchors, capturing
+// and non-capturing groups, alternation and greedy or lazy quantifiers.
+
+#[derive(Debug)]
+pub struct RegexError(String);
+
+impl fmt::Display for RegexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum ClassItem {
+    Range(char, char),
+    Digit(bool),
+    Word(bool),
+    Space(bool),
+}
+
+impl ClassItem {
+    fn contains(&self, c: char) -> bool {
+        match *self {
+            ClassItem::Range(start, end) => (start..=end).contains(&c),
+            ClassItem::Digit(negated) => c.is_ascii_digit() != negated,
+            ClassItem::Word(negated) => is_word_char(c) != negated,
+            ClassItem::Space(negated) => c.is_whitespace() != negated,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Class {
+    items: Vec<ClassItem>,
+    negated: bool,
+}
+
+impl Class {
+    fn contains(&self, c: char, case_sensitive: bool) -> bool {
+        let found = |c: char| self.items.iter().any(|item| item.contains(c));
+
+        let contains = if case_sensitive {
+            found(c)
+        } else {
+            found(c) || c.to_lowercase().any(found) || c.to_uppercase().any(found)
+        };
+
+        contains != self.negated
+    }
+}
+
+#[derive(Debug)]
+struct Repeat {
+    node: Node,
+    min: usize,
+    max: Option<usize>,
+    greedy: bool,
+}
+
+#[derive(Debug)]
+enum Node {
+    Empty,
+    Char(char),
+    Any,
+    Class(Class),
+    LineStart,
+    LineEnd,
+    WordBoundary(bool),
+    Group(Box<Node>, Option<usize>),
+    Concat(Vec<Node>),
+    Alternate(Vec<Node>),
+    Repeat(Box<Repeat>),
+}
+
+struct Parser {
+    chars: Vec<char>,
+    position: usize,
+    groups: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.position).copied()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn next(&mut self) -> Result<char, RegexError> {
+        let c = self
+            .peek()
+            .ok_or_else(|| RegexError(String::from("unexpected end of pattern")))?;
+        self.position += 1;
+        Ok(c)
+    }
+
+    fn parse_alternation(&mut self) -> Result<Node, RegexError> {
+        let mut alternatives = vec![self.parse_concat()?];
+
+        while self.eat('|') {
+            alternatives.push(self.parse_concat()?);
+        }
+
+        Ok(if alternatives.len() == 1 {
+            alternatives.remove(0)
+        } else {
+            Node::Alternate(alternatives)
+        })
+    }
+
+    fn parse_concat(&mut self) -> Result<Node, RegexError> {
+        let mut nodes = Vec::new();
+
+        while let Some(c) = self.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+
+            nodes.push(self.parse_repeat()?);
+        }
+
+        Ok(match nodes.len() {
+            0 => Node::Empty,
+            1 => nodes.remove(0),
+            _ => Node::Concat(nodes),
+        })
+    }
+
+    fn parse_repeat(&mut self) -> Result<Node, RegexError> {
+        let mut node = self.parse_atom()?;
+
+        while let Some((min, max)) = self.parse_quantifier() {
+            let greedy = !self.eat('?');
+            node = Node::Repeat(Box::new(Repeat {
+                node,
+                min,
+                max,
+                greedy,
+            }));
+        }
+
+        Ok(node)
+    }
+
+    // Consumes `*`, `+`, `?`, `{n}`, `{n,}` or `{n,m}`, leaving a `{` that
+    // isn't a valid quantifier to be matched literally
+    fn parse_quantifier(&mut self) -> Option<(usize, Option<usize>)> {
+        let bounds = match self.peek()? {
+            '*' => (0, None),
+            '+' => (1, None),
+            '?' => (0, Some(1)),
+            '{' => {
+                let rest: String = self.chars[self.position..].iter().collect();
+                let end = rest.find('}')?;
+                let inner = &rest[1..end];
+
+                let bounds = match inner.split_once(',') {
+                    None => {
+                        let n = inner.parse().ok()?;
+                        (n, Some(n))
+                    }
+                    Some((min, "")) => (min.parse().ok()?, None),
+                    Some((min, max)) => (min.parse().ok()?, Some(max.parse().ok()?)),
+                };
+
+                self.position += inner.chars().count() + 1;
+                bounds
+            }
+            _ => return None,
+        };
+
+        self.position += 1;
+        Some(bounds)
+    }
+
+    fn parse_atom(&mut self) -> Result<Node, RegexError> {
+        match self.next()? {
+            '(' => {
+                let index = if self.eat('?') {
+                    if !self.eat(':') {
+                        return Err(RegexError(String::from("unsupported group syntax")));
+                    }
+                    None
+                } else {
+                    self.groups += 1;
+                    Some(self.groups)
+                };
+
+                let inner = self.parse_alternation()?;
+
+                if !self.eat(')') {
+                    return Err(RegexError(String::from("unclosed group")));
+                }
+
+                Ok(Node::Group(Box::new(inner), index))
+            }
+            '[' => self.parse_class(),
+            '.' => Ok(Node::Any),
+            '^' => Ok(Node::LineStart),
+            '$' => Ok(Node::LineEnd),
+            '*' | '+' | '?' => Err(RegexError(String::from("nothing to repeat"))),
+            '\\' => match self.next()? {
+                'b' => Ok(Node::WordBoundary(true)),
+                'B' => Ok(Node::WordBoundary(false)),
+                c => Ok(match Self::escape(c)? {
+                    ClassItem::Range(c, _) => Node::Char(c),
+                    item => Node::Class(Class {
+                        items: vec![item],
+                        negated: false,
+                    }),
+                }),
+            },
+            c => Ok(Node::Char(c)),
+        }
+    }
+
+    fn parse_class(&mut self) -> Result<Node, RegexError> {
+        let negated = self.eat('^');
+        let mut items = Vec::new();
+
+        loop {
+            let c = self
+                .next()
+                .map_err(|_| RegexError(String::from("unclosed character class")))?;
+
+            if c == ']' && !items.is_empty() {
+                break;
+            }
+
+            let start = if c == '\\' {
+                match Self::escape(self.next()?)? {
+                    ClassItem::Range(c, _) => c,
+                    item => {
+                        items.push(item);
+                        continue;
+                    }
+                }
+            } else {
+                c
+            };
+
+            let is_range = self.peek() == Some('-')
+                && self.chars.get(self.position + 1).is_some_and(|&c| c != ']');
+
+            if is_range {
+                self.position += 1;
+
+                let end = match self.next()? {
+                    '\\' => match Self::escape(self.next()?)? {
+                        ClassItem::Range(c, _) => c,
+                        _ => return Err(RegexError(String::from("invalid class range"))),
+                    },
+                    c => c,
+                };
+
+                if end < start {
+                    return Err(RegexError(String::from("invalid class range")));
+                }
+
+                items.push(ClassItem::Range(start, end));
+            } else {
+                items.push(ClassItem::Range(start, start));
+            }
+        }
+
+        Ok(Node::Class(Class { items, negated }))
+    }
+
+    fn escape(c: char) -> Result<ClassItem, RegexError> {
+        let literal = |c| Ok(ClassItem::Range(c, c));
+
+        match c {
+            'd' => Ok(ClassItem::Digit(false)),
+            'D' => Ok(ClassItem::Digit(true)),
+            'w' => Ok(ClassItem::Word(false)),
+            'W' => Ok(ClassItem::Word(true)),
+            's' => Ok(ClassItem::Space(false)),
+            'S' => Ok(ClassItem::Space(true)),
+            'n' => literal('\n'),
+            't' => literal('\t'),
+            'r' => literal('\r'),
+            c if c.is_alphanumeric() => Err(RegexError(format!("unsupported escape \\{}", c))),
+            c => literal(c),
+        }
+    }
+}
+
+type Slots = Vec<Option<Range<usize>>>;
+
+struct Matcher<'a> {
+    text: &'a str,
+    case_sensitive: bool,
+    depth: Cell<usize>,
+    too_deep: Cell<bool>,
+}
+
+impl Matcher<'_> {
+    fn next_char(&self, position: usize) -> Option<char> {
+        self.text[position..].chars().next()
+    }
+
+    fn previous_char(&self, position: usize) -> Option<char> {
+        self.text[..position].chars().next_back()
+    }
+
+    fn chars_equal(&self, a: char, b: char) -> bool {
+        a == b || (!self.case_sensitive && a.to_lowercase().eq(b.to_lowercase()))
+    }
+
+    // End of the single character that `node` matches at `position`, for
+    // nodes that always match exactly one character
+    fn match_char(&self, node: &Node, position: usize) -> Option<Option<usize>> {
+        let matched = match node {
+            Node::Char(expected) => self
+                .next_char(position)
+                .filter(|&c| self.chars_equal(*expected, c)),
+            Node::Any => self.next_char(position).filter(|&c| c != '\n'),
+            Node::Class(class) => self
+                .next_char(position)
+                .filter(|&c| class.contains(c, self.case_sensitive)),
+            _ => return None,
+        };
+
+        Some(matched.map(|c| position + c.len_utf8()))
+    }
+
+    fn match_node(
+        &self,
+        node: &Node,
+        position: usize,
+        slots: &mut Slots,
+        next: &mut dyn FnMut(usize, &mut Slots) -> bool,
+    ) -> bool {
+        if self.too_deep.get() || self.depth.get() >= MAX_DEPTH {
+            self.too_deep.set(true);
+            return false;
+        }
+
+        self.depth.set(self.depth.get() + 1);
+        let matched = self.match_node_inner(node, position, slots, next);
+        self.depth.set(self.depth.get() - 1);
+
+        matched
+    }
+
+    fn match_node_inner(
+        &self,
+        node: &Node,
+        position: usize,
+        slots: &mut Slots,
+        next: &mut dyn FnMut(usize, &mut Slots) -> bool,
+    ) -> bool {
+        match node {
+            Node::Empty => next(position, slots),
+            Node::Char(expected) => match self.next_char(position) {
+                Some(c) if self.chars_equal(*expected, c) => next(position + c.len_utf8(), slots),
+                _ => false,
+            },
+            Node::Any => match self.next_char(position) {
+                Some(c) if c != '\n' => next(position + c.len_utf8(), slots),
+                _ => false,
+            },
+            Node::Class(class) => match self.next_char(position) {
+                Some(c) if class.contains(c, self.case_sensitive) => {
+                    next(position + c.len_utf8(), slots)
+                }
+                _ => false,
+            },
+            Node::LineStart => {
+                matches!(self.previous_char(position), None | Some('\n')) && next(position, slots)
+            }
+            Node::LineEnd => {
+                matches!(self.next_char(position), None | Some('\n')) && next(position, slots)
+            }
+            Node::WordBoundary(expected) => {
+                let before = self.previous_char(position).is_some_and(is_word_char);
+                let after = self.next_char(position).is_some_and(is_word_char);
+
+                (before != after) == *expected && next(position, slots)
+            }
+            Node::Group(inner, None) => self.match_node(inner, position, slots, next),
+            Node::Group(inner, Some(index)) => {
+                self.match_node(inner, position, slots, &mut |end, slots| {
+                    let previous = slots[*index].replace(position..end);
+
+                    if next(end, slots) {
+                        return true;
+                    }
+
+                    slots[*index] = previous;
+                    false
+                })
+            }
+            Node::Concat(nodes) => self.match_sequence(nodes, position, slots, next),
+            Node::Alternate(alternatives) => alternatives
+                .iter()
+                .any(|alternative| self.match_node(alternative, position, slots, next)),
+            Node::Repeat(repeat) if self.match_char(&repeat.node, position).is_some() => {
+                self.match_char_repeat(repeat, position, slots, next)
+            }
+            Node::Repeat(repeat) => self.match_repeat(repeat, 0, position, slots, next),
+        }
+    }
+
+    // Repeats of a single character are matched in a loop rather than by
+    // recursing for every character, so that `.*` can run over long lines
+    fn match_char_repeat(
+        &self,
+        repeat: &Repeat,
+        position: usize,
+        slots: &mut Slots,
+        next: &mut dyn FnMut(usize, &mut Slots) -> bool,
+    ) -> bool {
+        let max = repeat.max.unwrap_or(usize::MAX);
+        let mut ends = vec![position];
+
+        while ends.len() <= max {
+            match self.match_char(&repeat.node, ends[ends.len() - 1]) {
+                Some(Some(end)) => ends.push(end),
+                _ => break,
+            }
+        }
+
+        if ends.len() <= repeat.min {
+            return false;
+        }
+
+        let mut candidates = ends[repeat.min..].iter();
+        if repeat.greedy {
+            candidates.rev().any(|&end| next(end, slots))
+        } else {
+            candidates.any(|&end| next(end, slots))
+        }
+    }
+
+    fn match_sequence(
+        &self,
+        nodes: &[Node],
+        position: usize,
+        slots: &mut Slots,
+        next: &mut dyn FnMut(usize, &mut Slots) -> bool,
+    ) -> bool {
+        match nodes.split_first() {
+            None => next(position, slots),
+            Some((first, rest)) => self.match_node(first, position, slots, &mut |end, slots| {
+                self.match_sequence(rest, end, slots, next)
+            }),
+        }
+    }
+
+    fn match_repeat(
+        &self,
+        repeat: &Repeat,
+        count: usize,
+        position: usize,
+        slots: &mut Slots,
+        next: &mut dyn FnMut(usize, &mut Slots) -> bool,
+    ) -> bool {
+        let can_stop = count >= repeat.min;
+        let can_continue = repeat.max.is_none_or(|max| count < max);
+
+        // Greedy repeats try another iteration first, lazy ones try to stop
+        if repeat.greedy {
+            if can_continue && self.repeat_once(repeat, count, position, slots, next) {
+                return true;
+            }
+
+            can_stop && next(position, slots)
+        } else {
+            if can_stop && next(position, slots) {
+                return true;
+            }
+
+            can_continue && self.repeat_once(repeat, count, position, slots, next)
+        }
+    }
+
+    fn repeat_once(
+        &self,
+        repeat: &Repeat,
+        count: usize,
+        position: usize,
+        slots: &mut Slots,
+        next: &mut dyn FnMut(usize, &mut Slots) -> bool,
+    ) -> bool {
+        self.match_node(&repeat.node, position, slots, &mut |end, slots| {
+            // An empty iteration would otherwise repeat forever
+            if end == position && count >= repeat.min {
+                return false;
+            }
+
+            self.match_repeat(repeat, count + 1, end, slots, next)
+        })
+    }
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+#[derive(Debug, Clone)]
+pub struct Captures {
+    slots: Slots,
+}
+
+impl Captures {
+    pub fn range(&self) -> Range<usize> {
+        self.slots[0].clone().unwrap_or_default()
+    }
+
+    pub fn get(&self, index: usize) -> Option<Range<usize>> {
+        self.slots.get(index).cloned().flatten()
+    }
+
+    // Expands `$n` and `${n}` group references in `template`, with `$$`
+    // standing for a literal dollar sign
+    pub fn expand(&self, text: &str, template: &str) -> String {
+        let mut expanded = String::new();
+        let mut rest = template;
+
+        while let Some(dollar) = rest.find('$') {
+            expanded.push_str(&rest[..dollar]);
+            rest = &rest[dollar + 1..];
+
+            let (digits, consumed) = if let Some(braced) = rest.strip_prefix('{') {
+                match braced.find('}') {
+                    Some(end) => (&braced[..end], end + 2),
+                    None => ("", 0),
+                }
+            } else {
+                let end = rest
+                    .find(|c: char| !c.is_ascii_digit())
+                    .unwrap_or(rest.len());
+                (&rest[..end], end)
+            };
+
+            match digits.parse::<usize>() {
+                Ok(index) => {
+                    if let Some(range) = self.get(index) {
+                        expanded.push_str(&text[range]);
+                    }
+                    rest = &rest[consumed..];
+                }
+                Err(_) if rest.starts_with('$') => {
+                    expanded.push('$');
+                    rest = &rest[1..];
+                }
+                Err(_) => expanded.push('$'),
+            }
+        }
+
+        expanded.push_str(rest);
+        expanded
+    }
+}
+
+#[derive(Debug)]
+pub struct Regex {
+    node: Node,
+    groups: usize,
+    case_sensitive: bool,
+}
+
+impl Regex {
+    pub fn new(pattern: &str, case_sensitive: bool) -> Result<Self, RegexError> {
+        let mut parser = Parser {
+            chars: pattern.chars().collect(),
+            position: 0,
+            groups: 0,
+        };
+
+        let node = parser.parse_alternation()?;
+
+        if parser.position < parser.chars.len() {
+            return Err(RegexError(String::from("unmatched closing parenthesis")));
+        }
+
+        Ok(Self {
+            node,
+            groups: parser.groups,
+            case_sensitive,
+        })
+    }
+
+    pub fn escape(text: &str) -> String {
+        let mut escaped = String::with_capacity(text.len());
+
+        for c in text.chars() {
+            if "\\.+*?()|[]{}^$".contains(c) {
+                escaped.push('\\');
+            }
+            escaped.push(c);
+        }
+
+        escaped
+    }
+
+    // Leftmost match starting at or after `start`
+    pub fn captures_at(&self, text: &str, start: usize) -> Option<Captures> {
+        let matcher = Matcher {
+            text,
+            case_sensitive: self.case_sensitive,
+            depth: Cell::new(0),
+            too_deep: Cell::new(false),
+        };
+
+        for position in (start..=text.len()).filter(|&i| text.is_char_boundary(i)) {
+            let mut slots = vec![None; self.groups + 1];
+            let mut end = position;
+
+            let matched =
+                matcher.match_node(&self.node, position, &mut slots, &mut |match_end, _| {
+                    end = match_end;
+                    true
+                });
+
+            // Whatever matched after giving up partway may not be the right
+            // match
+            if matcher.too_deep.get() {
+                return None;
+            }
+
+            if matched {
+                slots[0] = Some(position..end);
+                return Some(Captures { slots });
+            }
+        }
+
+        None
+    }
+
+    pub fn find_all(&self, text: &str) -> Vec<Range<usize>> {
+        let mut matches = Vec::new();
+        let mut start = 0;
+
+        while let Some(captures) = self.captures_at(text, start) {
+            let range = captures.range();
+
+            start = if range.is_empty() {
+                match text[range.end..].chars().next() {
+                    Some(c) => range.end + c.len_utf8(),
+                    None => text.len() + 1,
+                }
+            } else {
+                range.end
+            };
+
+            matches.push(range);
+
+            if start > text.len() {
+                break;
+            }
+        }
+
+        matches
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find(pattern: &str, text: &str) -> Option<Range<usize>> {
+        Regex::new(pattern, true)
+            .unwrap()
+            .captures_at(text, 0)
+            .map(|captures| captures.range())
+    }
+
+    #[test]
+    fn captures() {
+        let text = "key = value";
+        let captures = Regex::new(r"(\w+) = (\w+)", true)
+            .unwrap()
+            .captures_at(text, 0)
+            .unwrap();
+
+        assert_eq!(captures.range(), 0..11);
+        assert_eq!(captures.get(1), Some(0..3));
+        assert_eq!(captures.get(2), Some(6..11));
+        assert_eq!(captures.get(3), None);
+    }
+
+    #[test]
+    fn greedy_and_lazy() {
+        assert_eq!(find("<.*>", "<a><b>"), Some(0..6));
+        assert_eq!(find("<.*?>", "<a><b>"), Some(0..3));
+        assert_eq!(find("(?:ab)+", "ababa"), Some(0..4));
+        assert_eq!(find("a{2,3}", "aaaa"), Some(0..3));
+        assert_eq!(find("a{2,3}?", "aaaa"), Some(0..2));
+    }
+
+    #[test]
+    fn word_boundaries() {
+        assert_eq!(find(r"\bcat\b", "concat cat"), Some(7..10));
+        assert_eq!(find(r"\Bcat", "cat concat"), Some(7..10));
+    }
+
+    #[test]
+    fn case_insensitive() {
+        let regex = Regex::new("[a-c]+x", false).unwrap();
+
+        assert_eq!(regex.captures_at("ABCX", 0).unwrap().range(), 0..4);
+    }
+
+    #[test]
+    fn expand() {
+        let text = "left right";
+        let captures = Regex::new(r"(\w+) (\w+)", true)
+            .unwrap()
+            .captures_at(text, 0)
+            .unwrap();
+
+        assert_eq!(captures.expand(text, "$2 $1"), "right left");
+        assert_eq!(captures.expand(text, "${1}s"), "lefts");
+        assert_eq!(captures.expand(text, "$$1 $"), "$1 $");
+    }
+
+    #[test]
+    fn invalid_patterns() {
+        assert!(Regex::new("(a", true).is_err());
+        assert!(Regex::new("a)", true).is_err());
+        assert!(Regex::new("*a", true).is_err());
+        assert!(Regex::new("[b-a]", true).is_err());
+    }
+
+    #[test]
+    fn long_input() {
+        let text = "a".repeat(50_000);
+        assert_eq!(find(".*", &text), Some(0..50_000));
+        assert_eq!(find("a*b", &text[..2000]), None);
+
+        // Too deep to match, but mustn't overflow the stack
+        let text = "ab".repeat(50_000);
+        assert_eq!(find("(ab)*", &text), None);
+        assert_eq!(find("(ab)*", "ababc"), Some(0..4));
+    }
+}
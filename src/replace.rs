@@ -0,0 +1,87 @@
+use std::ops::Range;
+
+use crate::regex::{Captures, Regex, RegexError};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplaceStage {
+    Pattern,
+    Replacement,
+    Confirm,
+}
+
+#[derive(Debug)]
+pub struct Replace {
+    pub stage: ReplaceStage,
+    pub pattern: String,
+    pub replacement: String,
+    pub use_regex: bool,
+    pub case_sensitive: bool,
+    pub whole_word: bool,
+    pub regex: Option<Regex>,
+    pub current: Option<(Range<usize>, String)>,
+    pub replaced: usize,
+    // Where the replace started, and whether it has wrapped around from the
+    // end of the buffer to finish the part before it
+    pub origin: usize,
+    pub wrapped: bool,
+}
+
+impl Default for Replace {
+    fn default() -> Self {
+        Self {
+            stage: ReplaceStage::Pattern,
+            pattern: String::new(),
+            replacement: String::new(),
+            use_regex: false,
+            case_sensitive: false,
+            whole_word: false,
+            regex: None,
+            current: None,
+            replaced: 0,
+            origin: 0,
+            wrapped: false,
+        }
+    }
+}
+
+impl Replace {
+    pub fn compile(&mut self) -> Result<(), RegexError> {
+        let mut pattern = if self.use_regex {
+            self.pattern.clone()
+        } else {
+            Regex::escape(&self.pattern)
+        };
+
+        if self.whole_word {
+            pattern = format!("\\b(?:{})\\b", pattern);
+        }
+
+        self.regex = Some(Regex::new(&pattern, self.case_sensitive)?);
+        Ok(())
+    }
+
+    pub fn expand(&self, line: &str, captures: &Captures) -> String {
+        if self.use_regex {
+            captures.expand(line, &self.replacement)
+        } else {
+            self.replacement.clone()
+        }
+    }
+
+    pub fn options(&self) -> String {
+        let options: Vec<&str> = [
+            (self.use_regex, "regex"),
+            (self.case_sensitive, "case-sensitive"),
+            (self.whole_word, "whole word"),
+        ]
+        .into_iter()
+        .filter_map(|(enabled, name)| enabled.then_some(name))
+        .collect();
+
+        if options.is_empty() {
+            String::new()
+        } else {
+            format!(" ({})", options.join(", "))
+        }
+    }
+}
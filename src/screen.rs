@@ -0,0 +1,274 @@
+use std::io::Write;
+
+use crossterm::{
+    cursor, queue,
+    style::{Attribute, Color, Print, SetAttribute, SetBackgroundColor, SetForegroundColor},
+    terminal::{Clear, ClearType},
+};
+
+use crate::unicode;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Style {
+    pub fg: Color,
+    pub bg: Color,
+    pub reverse: bool,
+}
+
+impl Style {
+    pub fn fg(fg: Color) -> Self {
+        Self {
+            fg,
+            ..Self::default()
+        }
+    }
+}
+
+impl Default for Style {
+    fn default() -> Self {
+        Self {
+            fg: Color::Reset,
+            bg: Color::Reset,
+            reverse: false,
+        }
+    }
+}
+
+// An empty symbol marks the second column of a wide grapheme
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Cell {
+    symbol: String,
+    style: Style,
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        Self {
+            symbol: String::from(" "),
+            style: Style::default(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Screen {
+    width: u16,
+    height: u16,
+    cells: Vec<Cell>,
+    previous: Vec<Cell>,
+    cursor: Option<(u16, u16)>,
+    redraw: bool,
+    clipboard: Option<String>,
+    // Column that writes stop at, so that a window can't draw over the one to
+    // its right
+    clip: Option<u16>,
+}
+
+impl Screen {
+    pub fn new(width: u16, height: u16) -> Self {
+        let size = width as usize * height as usize;
+
+        Self {
+            width,
+            height,
+            cells: vec![Cell::default(); size],
+            previous: vec![Cell::default(); size],
+            cursor: None,
+            redraw: true,
+            clipboard: None,
+            clip: None,
+        }
+    }
+
+    pub fn width(&self) -> u16 {
+        self.width
+    }
+
+    pub fn height(&self) -> u16 {
+        self.height
+    }
+
+    pub fn resize(&mut self, width: u16, height: u16) {
+        if (width, height) != (self.width, self.height) {
+            *self = Self::new(width, height);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.cells.fill(Cell::default());
+        self.cursor = None;
+    }
+
+    pub fn set_cursor(&mut self, position: Option<(u16, u16)>) {
+        self.cursor = position;
+    }
+
+    // Sends the text to the system clipboard with an OSC 52 escape sequence on
+    // the next flush, which also works over SSH
+    pub fn set_clipboard(&mut self, text: &str) {
+        self.clipboard = Some(base64(text.as_bytes()));
+    }
+
+    pub fn set_clip(&mut self, clip: Option<u16>) {
+        self.clip = clip;
+    }
+
+    pub fn fill(&mut self, x: u16, y: u16, width: u16, style: Style) {
+        if y >= self.height {
+            return;
+        }
+
+        for x in x..x.saturating_add(width).min(self.right()) {
+            self.set_cell(x, y, " ", style);
+        }
+    }
+
+    // Writes `text` starting at column `x`, clipped to the right edge of the
+    // screen, and returns the column after the last grapheme written
+    pub fn put_str(&mut self, x: u16, y: u16, text: &str, style: Style) -> u16 {
+        if y >= self.height {
+            return x;
+        }
+
+        let right = self.right();
+        let mut x = x;
+
+        for (_, grapheme) in unicode::grapheme_indices(text) {
+            let width = unicode::grapheme_width(grapheme) as u16;
+
+            if width == 0 {
+                continue;
+            }
+
+            if x + width > right {
+                // A wide grapheme that doesn't fit is padded instead
+                while x < right {
+                    self.set_cell(x, y, " ", style);
+                    x += 1;
+                }
+                break;
+            }
+
+            if grapheme.chars().any(char::is_control) {
+                self.set_cell(x, y, "\u{FFFD}", style);
+            } else {
+                self.set_cell(x, y, grapheme, style);
+            }
+
+            for continuation in x + 1..x + width {
+                self.set_cell(continuation, y, "", style);
+            }
+
+            x += width;
+        }
+
+        x
+    }
+
+    pub fn flush(&mut self, out: &mut impl Write) -> std::io::Result<()> {
+        queue!(out, cursor::Hide)?;
+
+        if self.redraw {
+            queue!(out, SetBackgroundColor(Color::Reset), Clear(ClearType::All))?;
+        }
+
+        let mut position = None;
+        let mut style = None;
+
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let index = self.index(x, y);
+                let cell = &self.cells[index];
+
+                if cell.symbol.is_empty() || (!self.redraw && *cell == self.previous[index]) {
+                    continue;
+                }
+
+                if position != Some((x, y)) {
+                    queue!(out, cursor::MoveTo(x, y))?;
+                }
+
+                if style != Some(cell.style) {
+                    let reverse = if cell.style.reverse {
+                        Attribute::Reverse
+                    } else {
+                        Attribute::NoReverse
+                    };
+
+                    queue!(
+                        out,
+                        SetForegroundColor(cell.style.fg),
+                        SetBackgroundColor(cell.style.bg),
+                        SetAttribute(reverse)
+                    )?;
+                    style = Some(cell.style);
+                }
+
+                queue!(out, Print(&cell.symbol))?;
+                position = Some((x + unicode::grapheme_width(&cell.symbol) as u16, y));
+            }
+        }
+
+        queue!(
+            out,
+            SetForegroundColor(Color::Reset),
+            SetBackgroundColor(Color::Reset),
+            SetAttribute(Attribute::NoReverse)
+        )?;
+
+        if let Some(clipboard) = self.clipboard.take() {
+            queue!(out, Print(format!("\x1b]52;c;{clipboard}\x07")))?;
+        }
+
+        if let Some((x, y)) = self.cursor {
+            queue!(out, cursor::MoveTo(x, y), cursor::Show)?;
+        }
+
+        out.flush()?;
+
+        self.previous.clone_from(&self.cells);
+        self.redraw = false;
+
+        Ok(())
+    }
+
+    fn set_cell(&mut self, x: u16, y: u16, symbol: &str, style: Style) {
+        let index = self.index(x, y);
+        let cell = &mut self.cells[index];
+
+        cell.symbol.clear();
+        cell.symbol.push_str(symbol);
+        cell.style = style;
+    }
+
+    fn right(&self) -> u16 {
+        self.clip.map_or(self.width, |clip| clip.min(self.width))
+    }
+
+    fn index(&self, x: u16, y: u16) -> usize {
+        y as usize * self.width as usize + x as usize
+    }
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &byte)| n | (byte as u32) << (16 - 8 * i));
+
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+
+    encoded
+}
@@ -0,0 +1,91 @@
+use std::ops::Range;
+
+#[derive(Debug)]
+pub struct Search {
+    pub query: String,
+    pub case_sensitive: bool,
+    pub origin: usize,
+    pub current: Option<Range<usize>>,
+}
+
+impl Search {
+    pub fn new(origin: usize, case_sensitive: bool) -> Self {
+        Self {
+            query: String::new(),
+            case_sensitive,
+            origin,
+            current: None,
+        }
+    }
+
+    // First match starting at or after `from`, wrapping around to the start
+    pub fn find_forward(&self, text: &str, from: usize) -> Option<Range<usize>> {
+        let from = from.min(text.len());
+
+        self.first_match(text, from, text.len())
+            .or_else(|| self.first_match(text, 0, from))
+    }
+
+    // Last match starting before `before`, wrapping around to the end
+    pub fn find_backward(&self, text: &str, before: usize) -> Option<Range<usize>> {
+        let before = before.min(text.len());
+
+        self.last_match(text, 0, before)
+            .or_else(|| self.last_match(text, before, text.len()))
+    }
+
+    pub fn find_all(&self, text: &str) -> Vec<Range<usize>> {
+        let mut matches = Vec::new();
+        let mut from = 0;
+
+        while let Some(range) = self.first_match(text, from, text.len()) {
+            from = range.end.max(range.start + 1);
+            matches.push(range);
+        }
+
+        matches
+    }
+
+    fn first_match(&self, text: &str, start: usize, end: usize) -> Option<Range<usize>> {
+        if self.query.is_empty() {
+            return None;
+        }
+
+        boundaries(text, start, end).find_map(|i| self.match_at(text, i))
+    }
+
+    fn last_match(&self, text: &str, start: usize, end: usize) -> Option<Range<usize>> {
+        if self.query.is_empty() {
+            return None;
+        }
+
+        boundaries(text, start, end)
+            .rev()
+            .find_map(|i| self.match_at(text, i))
+    }
+
+    fn match_at(&self, text: &str, start: usize) -> Option<Range<usize>> {
+        if self.case_sensitive {
+            return text[start..]
+                .starts_with(&self.query)
+                .then(|| start..start + self.query.len());
+        }
+
+        let mut chars = text[start..].char_indices();
+
+        for q in self.query.chars() {
+            let (_, c) = chars.next()?;
+
+            if !c.to_lowercase().eq(q.to_lowercase()) {
+                return None;
+            }
+        }
+
+        let end = chars.next().map_or(text.len(), |(i, _)| start + i);
+        Some(start..end)
+    }
+}
+
+fn boundaries(text: &str, start: usize, end: usize) -> impl DoubleEndedIterator<Item = usize> + '_ {
+    (start..end).filter(|&i| text.is_char_boundary(i))
+}
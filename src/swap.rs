@@ -0,0 +1,241 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    fs::{self, OpenOptions},
+    hash::{Hash, Hasher},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process,
+    time::{Duration, Instant},
+};
+
+use crate::file_format::FileFormat;
+
+const INTERVAL: Duration = Duration::from_secs(2);
+const HEADER: &str = "ete swap file, pid ";
+
+// Unsaved contents of a buffer kept next to the file, so that they can be
+// recovered if the editor or the terminal dies
+#[derive(Debug)]
+pub struct SwapFile {
+    path: PathBuf,
+    file_path: PathBuf,
+    last_write: Option<Instant>,
+    last_hash: Option<u64>,
+    // Set when another running editor owns the swap file, which is then left
+    // alone
+    disabled: bool,
+}
+
+impl SwapFile {
+    pub fn new(file_path: &Path) -> Self {
+        let file_path = fs::canonicalize(file_path).unwrap_or_else(|_| file_path.to_path_buf());
+        let file_name = file_path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        Self {
+            path: file_path.with_file_name(format!(".{}.swp", file_name)),
+            file_path,
+            last_write: None,
+            last_hash: None,
+            disabled: false,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn is_due(&self) -> bool {
+        !self.disabled
+            && self
+                .last_write
+                .is_none_or(|time| time.elapsed() >= INTERVAL)
+    }
+
+    pub fn disable(&mut self) {
+        self.disabled = true;
+    }
+
+    // Writes the contents unless they are the same as last time
+    pub fn write(&mut self, contents: &str) -> io::Result<()> {
+        if self.disabled {
+            return Ok(());
+        }
+
+        let mut hasher = DefaultHasher::new();
+        contents.hash(&mut hasher);
+        let hash = hasher.finish();
+
+        self.last_write = Some(Instant::now());
+        if self.last_hash == Some(hash) {
+            return Ok(());
+        }
+
+        // Write to the side first so that a crash mid write can't leave a
+        // truncated swap file behind
+        let temp_path = self.path.with_extension("swp.tmp");
+        let _ = fs::remove_file(&temp_path);
+
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+
+        // The unsaved contents are as private as the file itself
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+
+            let mode = fs::metadata(&self.file_path)
+                .map_or(0o600, |metadata| metadata.permissions().mode() & 0o777);
+            options.mode(mode);
+        }
+
+        let mut file = options.open(&temp_path)?;
+        write!(file, "{}{}\n{}", HEADER, process::id(), contents)?;
+        fs::rename(&temp_path, &self.path)?;
+
+        self.last_hash = Some(hash);
+        Ok(())
+    }
+
+    pub fn remove(&mut self) {
+        if self.disabled {
+            return;
+        }
+
+        if self.last_hash.take().is_some() || self.path.exists() {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+
+    // Reads a swap file left behind by an earlier session, returning the
+    // process that wrote it and the contents
+    pub fn read(&self) -> Option<(u32, String)> {
+        let swap = fs::read_to_string(&self.path).ok()?;
+        let (header, contents) = swap.split_once('\n')?;
+        let pid = header.strip_prefix(HEADER)?.parse().ok()?;
+
+        Some((pid, contents.to_string()))
+    }
+}
+
+// Whether the process that wrote a swap file is another editor that is still
+// running. Process ids get reused after a crash, so the process must also be
+// running the same program as this one, and this process can't be it. Only
+// Linux has /proc, elsewhere every swap file is treated as stale
+pub fn is_running(pid: u32) -> bool {
+    if pid == process::id() {
+        return false;
+    }
+
+    match (
+        fs::read_to_string(format!("/proc/{}/comm", pid)),
+        fs::read_to_string("/proc/self/comm"),
+    ) {
+        (Ok(comm), Ok(own_comm)) => comm == own_comm,
+        _ => false,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffKind {
+    Same,
+    Removed,
+    Added,
+}
+
+#[derive(Debug)]
+pub struct Recovery {
+    pub text: String,
+    pub format: FileFormat,
+    pub diff: Option<Vec<(DiffKind, String)>>,
+    pub scroll: usize,
+}
+
+impl Recovery {
+    pub fn new(contents: &str) -> Self {
+        let (format, text) = FileFormat::detect(contents);
+
+        Self {
+            text,
+            format,
+            diff: None,
+            scroll: 0,
+        }
+    }
+}
+
+// Line diff between two texts. The unchanged start and end are trimmed off
+// before finding the longest common subsequence of the rest, and a middle
+// that is too big for that is shown as replaced outright
+pub fn diff(old: &str, new: &str) -> Vec<(DiffKind, String)> {
+    let old: Vec<&str> = old.split('\n').collect();
+    let new: Vec<&str> = new.split('\n').collect();
+
+    let prefix = old.iter().zip(&new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let old_middle = &old[prefix..old.len() - suffix];
+    let new_middle = &new[prefix..new.len() - suffix];
+
+    let mut lines: Vec<(DiffKind, String)> = old[..prefix]
+        .iter()
+        .map(|line| (DiffKind::Same, line.to_string()))
+        .collect();
+
+    if old_middle.len() * new_middle.len() > 4_000_000 {
+        lines.extend(
+            old_middle
+                .iter()
+                .map(|line| (DiffKind::Removed, line.to_string())),
+        );
+        lines.extend(
+            new_middle
+                .iter()
+                .map(|line| (DiffKind::Added, line.to_string())),
+        );
+    } else {
+        // lengths[i][j] is the longest common subsequence of old_middle[i..]
+        // and new_middle[j..]
+        let (n, m) = (old_middle.len(), new_middle.len());
+        let mut lengths = vec![vec![0usize; m + 1]; n + 1];
+        for i in (0..n).rev() {
+            for j in (0..m).rev() {
+                lengths[i][j] = if old_middle[i] == new_middle[j] {
+                    lengths[i + 1][j + 1] + 1
+                } else {
+                    lengths[i + 1][j].max(lengths[i][j + 1])
+                };
+            }
+        }
+
+        let (mut i, mut j) = (0, 0);
+        while i < n || j < m {
+            if i < n && j < m && old_middle[i] == new_middle[j] {
+                lines.push((DiffKind::Same, old_middle[i].to_string()));
+                i += 1;
+                j += 1;
+            } else if j == m || (i < n && lengths[i + 1][j] >= lengths[i][j + 1]) {
+                lines.push((DiffKind::Removed, old_middle[i].to_string()));
+                i += 1;
+            } else {
+                lines.push((DiffKind::Added, new_middle[j].to_string()));
+                j += 1;
+            }
+        }
+    }
+
+    lines.extend(
+        old[old.len() - suffix..]
+            .iter()
+            .map(|line| (DiffKind::Same, line.to_string())),
+    );
+
+    lines
+}
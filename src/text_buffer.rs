@@ -0,0 +1,363 @@
+use std::{fmt, ops::Range};
+
+// A piece table: the document is a sequence of pieces, each referring to a
+// span of either the original file contents or an append-only buffer of
+// everything inserted since. Edits only touch the piece list, so their cost
+// does not grow with the size of the file.
+
+#[derive(Debug, Default)]
+struct Source {
+    text: String,
+    line_breaks: Vec<usize>,
+}
+
+impl Source {
+    fn new(text: String) -> Self {
+        let line_breaks = text.match_indices('\n').map(|(i, _)| i).collect();
+
+        Self { text, line_breaks }
+    }
+
+    fn push_str(&mut self, text: &str) -> usize {
+        let start = self.text.len();
+
+        self.line_breaks
+            .extend(text.match_indices('\n').map(|(i, _)| start + i));
+        self.text.push_str(text);
+
+        start
+    }
+
+    fn count_line_breaks(&self, range: Range<usize>) -> usize {
+        self.line_breaks.partition_point(|&i| i < range.end)
+            - self.line_breaks.partition_point(|&i| i < range.start)
+    }
+
+    fn nth_line_break(&self, start: usize, n: usize) -> usize {
+        self.line_breaks[self.line_breaks.partition_point(|&i| i < start) + n]
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SourceKind {
+    Original,
+    Added,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Piece {
+    source: SourceKind,
+    start: usize,
+    len: usize,
+    line_breaks: usize,
+}
+
+#[derive(Debug)]
+pub struct TextBuffer {
+    original: Source,
+    added: Source,
+    pieces: Vec<Piece>,
+    len: usize,
+    line_breaks: usize,
+}
+
+impl TextBuffer {
+    pub fn new(text: String) -> Self {
+        let original = Source::new(text);
+        let len = original.text.len();
+        let line_breaks = original.line_breaks.len();
+
+        let pieces = if len > 0 {
+            vec![Piece {
+                source: SourceKind::Original,
+                start: 0,
+                len,
+                line_breaks,
+            }]
+        } else {
+            Vec::new()
+        };
+
+        Self {
+            original,
+            added: Source::default(),
+            pieces,
+            len,
+            line_breaks,
+        }
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.line_breaks + 1
+    }
+
+    pub fn insert(&mut self, offset: usize, text: &str) {
+        assert!(offset <= self.len, "insert offset out of bounds");
+
+        if text.is_empty() {
+            return;
+        }
+
+        let start = self.added.push_str(text);
+        let piece = self.make_piece(SourceKind::Added, start, text.len());
+        let (index, piece_start) = self.locate(offset);
+
+        self.len += piece.len;
+        self.line_breaks += piece.line_breaks;
+
+        if offset == piece_start {
+            // Typing sequentially just grows the previous piece
+            if let Some(previous) = index.checked_sub(1).map(|i| &mut self.pieces[i]) {
+                if previous.source == SourceKind::Added && previous.start + previous.len == start {
+                    previous.len += piece.len;
+                    previous.line_breaks += piece.line_breaks;
+                    return;
+                }
+            }
+
+            self.pieces.insert(index, piece);
+        } else {
+            let split = self.pieces[index];
+            let left_len = offset - piece_start;
+
+            let left = self.make_piece(split.source, split.start, left_len);
+            let right = self.make_piece(split.source, split.start + left_len, split.len - left_len);
+
+            self.pieces.splice(index..=index, [left, piece, right]);
+        }
+    }
+
+    pub fn delete(&mut self, range: Range<usize>) {
+        assert!(
+            range.start <= range.end && range.end <= self.len,
+            "delete range out of bounds"
+        );
+
+        if range.is_empty() {
+            return;
+        }
+
+        let mut pieces = Vec::with_capacity(self.pieces.len() + 1);
+        let mut piece_start = 0;
+
+        for piece in &self.pieces {
+            let piece_end = piece_start + piece.len;
+
+            if piece_end <= range.start || piece_start >= range.end {
+                pieces.push(*piece);
+            } else {
+                if piece_start < range.start {
+                    pieces.push(self.make_piece(
+                        piece.source,
+                        piece.start,
+                        range.start - piece_start,
+                    ));
+                }
+
+                if piece_end > range.end {
+                    let cut = range.end - piece_start;
+                    pieces.push(self.make_piece(piece.source, piece.start + cut, piece.len - cut));
+                }
+            }
+
+            piece_start = piece_end;
+        }
+
+        self.pieces = pieces;
+        self.len -= range.len();
+        self.line_breaks = self.pieces.iter().map(|piece| piece.line_breaks).sum();
+    }
+
+    pub fn slice(&self, range: Range<usize>) -> String {
+        let mut text = String::with_capacity(range.len());
+        let mut piece_start = 0;
+
+        for piece in &self.pieces {
+            let piece_end = piece_start + piece.len;
+
+            if piece_start >= range.end {
+                break;
+            }
+
+            if piece_end > range.start {
+                let from = range.start.max(piece_start) - piece_start;
+                let to = range.end.min(piece_end) - piece_start;
+                text.push_str(&self.piece_str(piece)[from..to]);
+            }
+
+            piece_start = piece_end;
+        }
+
+        text
+    }
+
+    pub fn line_to_offset(&self, line: usize) -> usize {
+        if line == 0 {
+            return 0;
+        }
+
+        let mut remaining = line;
+        let mut piece_start = 0;
+
+        for piece in &self.pieces {
+            if piece.line_breaks >= remaining {
+                let line_break = self
+                    .source(piece.source)
+                    .nth_line_break(piece.start, remaining - 1);
+
+                return piece_start + (line_break - piece.start) + 1;
+            }
+
+            remaining -= piece.line_breaks;
+            piece_start += piece.len;
+        }
+
+        self.len
+    }
+
+    pub fn offset_to_line(&self, offset: usize) -> usize {
+        let mut line = 0;
+        let mut piece_start = 0;
+
+        for piece in &self.pieces {
+            if offset < piece_start + piece.len {
+                let end = piece.start + (offset - piece_start);
+                line += self
+                    .source(piece.source)
+                    .count_line_breaks(piece.start..end);
+                break;
+            }
+
+            line += piece.line_breaks;
+            piece_start += piece.len;
+        }
+
+        line
+    }
+
+    pub fn line(&self, line: usize) -> String {
+        self.slice(self.line_to_offset(line)..self.line_end(line))
+    }
+
+    pub fn line_len(&self, line: usize) -> usize {
+        self.line_end(line) - self.line_to_offset(line)
+    }
+
+    fn line_end(&self, line: usize) -> usize {
+        if line + 1 < self.line_count() {
+            self.line_to_offset(line + 1) - 1
+        } else {
+            self.len
+        }
+    }
+
+    fn locate(&self, offset: usize) -> (usize, usize) {
+        let mut piece_start = 0;
+
+        for (index, piece) in self.pieces.iter().enumerate() {
+            if offset < piece_start + piece.len {
+                return (index, piece_start);
+            }
+
+            piece_start += piece.len;
+        }
+
+        (self.pieces.len(), self.len)
+    }
+
+    fn make_piece(&self, source: SourceKind, start: usize, len: usize) -> Piece {
+        Piece {
+            source,
+            start,
+            len,
+            line_breaks: self.source(source).count_line_breaks(start..start + len),
+        }
+    }
+
+    fn source(&self, kind: SourceKind) -> &Source {
+        match kind {
+            SourceKind::Original => &self.original,
+            SourceKind::Added => &self.added,
+        }
+    }
+
+    fn piece_str(&self, piece: &Piece) -> &str {
+        &self.source(piece.source).text[piece.start..piece.start + piece.len]
+    }
+}
+
+impl fmt::Display for TextBuffer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for piece in &self.pieces {
+            f.write_str(self.piece_str(piece))?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Builds "one\ntwo\nthree\n" out of pieces from both sources
+    fn pieced() -> TextBuffer {
+        let mut buffer = TextBuffer::new(String::from("one\nthree\n"));
+        buffer.insert(4, "tw");
+        buffer.insert(6, "o\n");
+        buffer
+    }
+
+    #[test]
+    fn empty() {
+        let buffer = TextBuffer::new(String::new());
+
+        assert_eq!(buffer.to_string(), "");
+        assert_eq!(buffer.line_count(), 1);
+        assert_eq!(buffer.line(0), "");
+        assert_eq!(buffer.line_to_offset(0), 0);
+        assert_eq!(buffer.offset_to_line(0), 0);
+    }
+
+    #[test]
+    fn insert_across_pieces() {
+        let buffer = pieced();
+
+        assert_eq!(buffer.to_string(), "one\ntwo\nthree\n");
+        assert_eq!(buffer.line_count(), 4);
+        assert_eq!(buffer.line(1), "two");
+        assert_eq!(buffer.line(3), "");
+        assert_eq!(buffer.slice(2..9), "e\ntwo\nt");
+    }
+
+    #[test]
+    fn delete_across_pieces() {
+        let mut buffer = pieced();
+
+        buffer.delete(2..9);
+        assert_eq!(buffer.to_string(), "onhree\n");
+        assert_eq!(buffer.line_count(), 2);
+
+        buffer.delete(0..7);
+        assert_eq!(buffer.to_string(), "");
+        assert_eq!(buffer.line_count(), 1);
+    }
+
+    #[test]
+    fn lines_and_offsets() {
+        let buffer = pieced();
+
+        assert_eq!(buffer.line_to_offset(0), 0);
+        assert_eq!(buffer.line_to_offset(1), 4);
+        assert_eq!(buffer.line_to_offset(2), 8);
+        assert_eq!(buffer.line_to_offset(3), 14);
+
+        assert_eq!(buffer.offset_to_line(3), 0);
+        assert_eq!(buffer.offset_to_line(4), 1);
+        assert_eq!(buffer.offset_to_line(7), 1);
+        assert_eq!(buffer.offset_to_line(8), 2);
+        assert_eq!(buffer.offset_to_line(14), 3);
+
+        assert_eq!(buffer.line_len(2), 5);
+    }
+}
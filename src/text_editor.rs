@@ -1,15 +1,30 @@
 use std::{
-    fs::{self, File},
-    io::{Stdout, Write},
-    path::PathBuf,
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    ops::Range,
+    path::{Path, PathBuf},
+    process,
+    time::{Duration, Instant},
 };
 
+use crate::{
+    disk_state::DiskState,
+    file_format::{FileFormat, LineEnding},
+    history::{Edit, EditKind, History},
+    kill_ring::KillRing,
+    layout::Rect,
+    message::{Message, MessageLevel},
+    motion,
+    replace::{Replace, ReplaceStage},
+    screen::{Screen, Style},
+    search::Search,
+    swap::{self, DiffKind, Recovery, SwapFile},
+    text_buffer::TextBuffer,
+    unicode, wrap,
+};
 use crossterm::{
-    cursor,
-    event::{KeyCode, KeyEvent, KeyModifiers},
-    execute,
-    style::{Color, Print, ResetColor, SetBackgroundColor, SetForegroundColor},
-    terminal::{self, Clear},
+    event::{KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEvent, MouseEventKind},
+    style::Color,
 };
 
 #[derive(Debug)]
@@ -17,10 +32,74 @@ pub struct TextEditor {
     pub alive: bool,
     pub path: PathBuf,
     pub saved: bool,
-    pub lines: Vec<String>,
+    pub message: Option<Message>,
+    pub buffer: TextBuffer,
+    pub format: FileFormat,
+    pub history: History,
+    pub view: View,
+    // Views of the other windows on this buffer, lent by the editor while it
+    // handles input so that edits can keep them on the same text
+    pub other_views: Vec<View>,
+    pub search: Option<Search>,
+    pub last_search: Option<Search>,
+    pub replace: Option<Replace>,
+    pub tab_width: usize,
+    pub expand_tab: bool,
+    pub soft_wrap: bool,
+    // Time, position and count of the last clicks, to detect double and
+    // triple clicks
+    pub last_click: Option<(Instant, u16, u16, u8)>,
+    pub swap: SwapFile,
+    pub recovery: Option<Recovery>,
+    pub disk_state: Option<DiskState>,
+    // New contents of the file after something else changed it, while asking
+    // what to do about it
+    pub disk_change: Option<String>,
+    pub disk_checked: Instant,
+}
+
+// Cursor, selection and scroll position of a window into the buffer. The
+// buffer holds the one for the focused window, and the others are swapped in
+// while drawing them
+#[derive(Debug, Clone)]
+pub struct View {
     pub cursor_row: usize,
     pub cursor_col: usize,
     pub cursor_col_offset: u16,
+    pub scroll_row: usize,
+    pub scroll_col: usize,
+    // Screen row within `scroll_row` that the view starts at when soft wrapping
+    pub scroll_segment: usize,
+    // Top left corner and size of the text area, leaving out the gutter and
+    // the toolbar
+    pub x: u16,
+    pub y: u16,
+    pub width: usize,
+    pub height: usize,
+    pub anchor: Option<usize>,
+    pub mark_active: bool,
+    // Display column that vertical movement tries to return to
+    pub desired_col: Option<usize>,
+}
+
+impl Default for View {
+    fn default() -> Self {
+        Self {
+            cursor_row: 0,
+            cursor_col: 0,
+            cursor_col_offset: 2,
+            scroll_row: 0,
+            scroll_col: 0,
+            scroll_segment: 0,
+            x: 0,
+            y: 0,
+            width: 0,
+            height: 0,
+            anchor: None,
+            mark_active: false,
+            desired_col: None,
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -31,74 +110,311 @@ pub enum Direction {
     Left,
     Front,
     Back,
+    SmartFront,
+    WordLeft,
+    WordRight,
+    ParagraphUp,
+    ParagraphDown,
+    MatchingBracket,
+    PageUp,
+    PageDown,
+    Top,
+    Bottom,
 }
 
 impl TextEditor {
     pub fn open_file(path: PathBuf) -> Result<Self, std::io::Error> {
-        if path.exists() {
+        let swap = SwapFile::new(&path);
+
+        let (saved, text, format, disk_state) = if path.exists() {
             let file_contents = fs::read_to_string(path.clone())?;
+            let disk_state = DiskState::new(&fs::metadata(&path)?, &file_contents);
+            let (format, text) = FileFormat::detect(&file_contents);
 
-            Ok(Self {
-                alive: true,
-                path,
-                saved: true,
-                lines: file_contents.lines().map(String::from).collect(),
-                cursor_row: 0,
-                cursor_col: 0,
-                cursor_col_offset: 2,
-            })
+            (true, text, format, Some(disk_state))
         } else {
-            Ok(Self {
-                alive: true,
-                path,
-                saved: false,
-                lines: vec![String::new()],
-                cursor_row: 0,
-                cursor_col: 0,
-                cursor_col_offset: 2,
-            })
+            (false, String::new(), FileFormat::default(), None)
+        };
+
+        let mut text_editor = Self {
+            alive: true,
+            path,
+            saved,
+            message: None,
+            buffer: TextBuffer::new(text),
+            format,
+            history: History::default(),
+            view: View::default(),
+            other_views: Vec::new(),
+            search: None,
+            last_search: None,
+            replace: None,
+            tab_width: 4,
+            expand_tab: false,
+            soft_wrap: false,
+            last_click: None,
+            swap,
+            recovery: None,
+            disk_state,
+            disk_change: None,
+            disk_checked: Instant::now(),
+        };
+
+        text_editor.check_swap();
+        Ok(text_editor)
+    }
+
+    // Offers to recover the contents of a swap file left behind by a session
+    // that didn't exit cleanly
+    fn check_swap(&mut self) {
+        let Some((pid, contents)) = self.swap.read() else {
+            return;
+        };
+
+        if swap::is_running(pid) {
+            self.swap.disable();
+            self.set_message(
+                MessageLevel::Warning,
+                format!(
+                    "Process {} is already editing this file, so no swap file is kept",
+                    pid
+                ),
+            );
+            return;
+        }
+
+        let recovery = Recovery::new(&contents);
+
+        if recovery.text == self.buffer.to_string() && recovery.format == self.format {
+            self.swap.remove();
+        } else {
+            self.recovery = Some(recovery);
         }
     }
 
-    pub fn handle_key(&mut self, event: KeyEvent) {
+    // The kill ring is shared by every buffer, so the editor passes it in
+    pub fn handle_key(&mut self, event: KeyEvent, kill_ring: &mut KillRing) {
+        if self.recovery.is_some() {
+            self.handle_recovery_key(event);
+            self.view.cursor_col_offset = self.get_line_number_width() + 1;
+            self.scroll_to_cursor();
+            return;
+        }
+
+        if self.disk_change.is_some() {
+            self.handle_disk_change_key(event);
+            self.view.cursor_col_offset = self.get_line_number_width() + 1;
+            self.scroll_to_cursor();
+            return;
+        }
+
+        if self.search.is_some() {
+            self.handle_search_key(event);
+            self.scroll_to_cursor();
+            return;
+        }
+
+        if self.replace.is_some() {
+            self.handle_replace_key(event);
+            self.scroll_to_cursor();
+            return;
+        }
+
+        let shift = event.modifiers.contains(KeyModifiers::SHIFT);
+        let yanked = kill_ring.yanked.take();
+        let killing = std::mem::take(&mut kill_ring.killing);
+
         match event.code {
             // Save
             KeyCode::Char('s') if event.modifiers.contains(KeyModifiers::CONTROL) => self.save(),
 
+            // Clear the selection, ignoring the empty one a click leaves
+            KeyCode::Esc if self.selection().is_some() || self.view.mark_active => {
+                self.clear_selection()
+            }
+            KeyCode::Char('g') if event.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.clear_selection()
+            }
+
             // Quit if saved
             KeyCode::Esc if self.saved => self.alive = false,
+            KeyCode::Esc => self.set_message(
+                MessageLevel::Warning,
+                "Unsaved changes! Save with Ctrl+S or discard them with Ctrl+Q",
+            ),
+
+            // Line editing
+            KeyCode::Up | KeyCode::Down
+                if event
+                    .modifiers
+                    .contains(KeyModifiers::ALT | KeyModifiers::SHIFT) =>
+            {
+                self.duplicate_line(event.code == KeyCode::Down)
+            }
+            KeyCode::Up if event.modifiers.contains(KeyModifiers::ALT) => self.move_line_up(),
+            KeyCode::Down if event.modifiers.contains(KeyModifiers::ALT) => self.move_line_down(),
+            KeyCode::Char('j') if event.modifiers.contains(KeyModifiers::ALT) => self.join_lines(),
+
+            // Word & paragraph motions
+            KeyCode::Left
+                if event
+                    .modifiers
+                    .intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) =>
+            {
+                self.navigate(Direction::WordLeft, shift)
+            }
+            KeyCode::Right
+                if event
+                    .modifiers
+                    .intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) =>
+            {
+                self.navigate(Direction::WordRight, shift)
+            }
+            KeyCode::Char('b') if event.modifiers.contains(KeyModifiers::ALT) => {
+                self.navigate(Direction::WordLeft, shift)
+            }
+            KeyCode::Char('f') if event.modifiers.contains(KeyModifiers::ALT) => {
+                self.navigate(Direction::WordRight, shift)
+            }
+            KeyCode::Up if event.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.navigate(Direction::ParagraphUp, shift)
+            }
+            KeyCode::Down if event.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.navigate(Direction::ParagraphDown, shift)
+            }
+            KeyCode::Char('{') if event.modifiers.contains(KeyModifiers::ALT) => {
+                self.navigate(Direction::ParagraphUp, shift)
+            }
+            KeyCode::Char('}') if event.modifiers.contains(KeyModifiers::ALT) => {
+                self.navigate(Direction::ParagraphDown, shift)
+            }
 
-            // Quit without saving
-            KeyCode::Char('q') if event.modifiers.contains(KeyModifiers::CONTROL) => {
-                self.alive = false
+            // Matching bracket, terminals send Ctrl+] as Ctrl+5
+            KeyCode::Char(']' | '5') if event.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.navigate(Direction::MatchingBracket, shift)
             }
 
             // Arrow keys
-            KeyCode::Up => self.move_cursor(Direction::Up),
-            KeyCode::Right => self.move_cursor(Direction::Right),
-            KeyCode::Down => self.move_cursor(Direction::Down),
-            KeyCode::Left => self.move_cursor(Direction::Left),
+            KeyCode::Up => self.navigate(Direction::Up, shift),
+            KeyCode::Right => self.navigate(Direction::Right, shift),
+            KeyCode::Down => self.navigate(Direction::Down, shift),
+            KeyCode::Left => self.navigate(Direction::Left, shift),
+
+            // Document start & end
+            KeyCode::Home if event.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.navigate(Direction::Top, shift)
+            }
+            KeyCode::End if event.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.navigate(Direction::Bottom, shift)
+            }
+            KeyCode::Char('<') if event.modifiers.contains(KeyModifiers::ALT) => {
+                self.navigate(Direction::Top, shift)
+            }
+            KeyCode::Char('>') if event.modifiers.contains(KeyModifiers::ALT) => {
+                self.navigate(Direction::Bottom, shift)
+            }
 
             // Home & end
-            KeyCode::Home => self.move_cursor(Direction::Front),
-            KeyCode::End => self.move_cursor(Direction::Back),
+            KeyCode::Home => self.navigate(Direction::SmartFront, shift),
+            KeyCode::Char('m') if event.modifiers.contains(KeyModifiers::ALT) => {
+                self.navigate(Direction::SmartFront, shift)
+            }
+            KeyCode::End => self.navigate(Direction::Back, shift),
+
+            // Page up & down
+            KeyCode::PageUp => self.navigate(Direction::PageUp, shift),
+            KeyCode::PageDown => self.navigate(Direction::PageDown, shift),
+
+            // Set or clear the mark
+            KeyCode::Char(' ') if event.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.toggle_mark()
+            }
 
             // Basic Emacs keys
             KeyCode::Char('a') if event.modifiers.contains(KeyModifiers::CONTROL) => {
-                self.move_cursor(Direction::Front)
+                self.navigate(Direction::Front, shift)
             }
             KeyCode::Char('e') if event.modifiers.contains(KeyModifiers::CONTROL) => {
-                self.move_cursor(Direction::Back)
+                self.navigate(Direction::Back, shift)
             }
             KeyCode::Char('u') if event.modifiers.contains(KeyModifiers::CONTROL) => {
-                self.clear_line()
+                self.clear_line(kill_ring, killing)
+            }
+            KeyCode::Char('k') if event.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.kill_to_line_end(kill_ring, killing)
+            }
+
+            // Clipboard
+            KeyCode::Char('c') if event.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.copy(kill_ring)
+            }
+            KeyCode::Char('x') if event.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.cut(kill_ring, killing)
+            }
+            KeyCode::Char('v' | 'y') if event.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.yank(kill_ring)
+            }
+            KeyCode::Char('y') if event.modifiers.contains(KeyModifiers::ALT) => {
+                self.yank_pop(kill_ring, yanked)
+            }
+
+            // File format
+            KeyCode::Char('l') if event.modifiers.contains(KeyModifiers::ALT) => {
+                self.toggle_line_ending()
+            }
+            KeyCode::Char('n') if event.modifiers.contains(KeyModifiers::ALT) => {
+                self.toggle_final_newline()
+            }
+            KeyCode::Char('u') if event.modifiers.contains(KeyModifiers::ALT) => self.toggle_bom(),
+            KeyCode::Char('t') if event.modifiers.contains(KeyModifiers::ALT) => {
+                self.toggle_expand_tab()
+            }
+            KeyCode::Char('w') if event.modifiers.contains(KeyModifiers::ALT) => {
+                self.toggle_soft_wrap()
+            }
+
+            // Search
+            KeyCode::Char('f') if event.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.start_search()
+            }
+
+            // Search and replace
+            KeyCode::Char('r' | '%') if event.modifiers.contains(KeyModifiers::ALT) => {
+                self.start_replace()
             }
 
+            // Undo & redo
+            KeyCode::Char('z') if event.modifiers.contains(KeyModifiers::CONTROL) => self.undo(),
+            KeyCode::Char('z') if event.modifiers.contains(KeyModifiers::ALT) => self.redo(),
+
             // New line
             KeyCode::Enter => self.insert_new_line(),
 
+            // Erase words
+            KeyCode::Backspace
+                if event
+                    .modifiers
+                    .intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) =>
+            {
+                self.kill_word_backward(kill_ring, killing)
+            }
+            KeyCode::Char('h') if event.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.kill_word_backward(kill_ring, killing)
+            }
+            KeyCode::Char('d') if event.modifiers.contains(KeyModifiers::ALT) => {
+                self.kill_word_forward(kill_ring, killing)
+            }
+
             // Erase text
             KeyCode::Backspace => self.erase_char(),
+            KeyCode::Delete => self.delete_char(),
+            KeyCode::Char('d') if event.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.delete_char()
+            }
+
+            // Indentation
+            KeyCode::Tab => self.indent(),
+            KeyCode::BackTab => self.dedent(),
 
             // Write text
             KeyCode::Char(c) => self.insert_char(c),
@@ -107,148 +423,2059 @@ impl TextEditor {
             _ => {}
         }
 
-        self.cursor_col_offset = self.get_line_number_width() + 1;
+        self.view.cursor_col_offset = self.get_line_number_width() + 1;
+        self.scroll_to_cursor();
     }
 
-    pub fn render(&self, out: &mut Stdout) -> Result<(), std::io::Error> {
-        let line_number_width = self.get_line_number_width();
+    // Whether keys are going to a prompt in the toolbar rather than the text
+    pub fn is_prompting(&self) -> bool {
+        self.search.is_some()
+            || self.replace.is_some()
+            || self.recovery.is_some()
+            || self.disk_change.is_some()
+    }
 
-        execute!(out, cursor::Hide)?;
+    // Inserts bracketed paste text in one edit instead of key by key
+    pub fn handle_paste(&mut self, text: String) {
+        // Terminals send line breaks in pastes as carriage returns
+        let text = text.replace("\r\n", "\n").replace('\r', "\n");
+        // Prompts only take a single line
+        let first_line = text.lines().next().unwrap_or_default();
 
-        for (row, line) in self.lines.iter().enumerate() {
-            let Ok(row) = row.try_into() else { break; };
+        if let Some(search) = &mut self.search {
+            search.query.push_str(first_line);
+            self.update_search();
+        } else if let Some(replace) = &mut self.replace {
+            match replace.stage {
+                ReplaceStage::Pattern => replace.pattern.push_str(first_line),
+                ReplaceStage::Replacement => replace.replacement.push_str(first_line),
+                ReplaceStage::Confirm => {}
+            }
+        } else if !text.is_empty() && !self.is_prompting() {
+            let offset = self.cursor_offset();
+            let range = self.selection().unwrap_or(offset..offset);
 
-            execute!(
-                out,
-                cursor::MoveTo(0, row),
-                SetForegroundColor(Color::Red),
-                Print(format!(
-                    "{:width$}",
-                    row + 1,
-                    width = line_number_width as usize
-                )),
-                ResetColor,
-                cursor::MoveTo(line_number_width + 1, row),
-                Print(line)
-            )?;
+            self.history.seal();
+            self.edit(range, &text, EditKind::Other);
+            self.view.cursor_col_offset = self.get_line_number_width() + 1;
         }
 
-        self.render_toolbar(out)?;
-
-        execute!(out, cursor::Show, ResetColor)?;
-        Ok(())
+        self.scroll_to_cursor();
     }
 
-    fn get_line_number_width(&self) -> u16 {
-        format!("{}", self.lines.len()).len() as u16
+    pub fn handle_mouse(&mut self, event: MouseEvent) {
+        if self.is_prompting() {
+            return;
+        }
+
+        match event.kind {
+            MouseEventKind::Down(MouseButton::Left) => {
+                let Some(offset) = self.offset_at(event.column, event.row) else {
+                    return;
+                };
+
+                let clicks = match self.last_click {
+                    Some((time, column, row, clicks))
+                        if time.elapsed() < Duration::from_millis(400)
+                            && (column, row) == (event.column, event.row) =>
+                    {
+                        clicks % 3 + 1
+                    }
+                    _ => 1,
+                };
+                self.last_click = Some((Instant::now(), event.column, event.row, clicks));
+
+                self.history.seal();
+                self.view.mark_active = false;
+
+                if event.modifiers.contains(KeyModifiers::SHIFT) {
+                    self.view.anchor.get_or_insert(self.cursor_offset());
+                    self.set_cursor_offset(offset);
+                    return;
+                }
+
+                match clicks {
+                    // Select the word under the pointer
+                    2 => {
+                        let row = self.buffer.offset_to_line(offset);
+                        let line_start = self.buffer.line_to_offset(row);
+                        let word = motion::word_at(&self.buffer.line(row), offset - line_start);
+
+                        self.view.anchor = Some(line_start + word.start);
+                        self.set_cursor_offset(line_start + word.end);
+                    }
+
+                    // Select the whole line
+                    3 => {
+                        let row = self.buffer.offset_to_line(offset);
+                        let line_start = self.buffer.line_to_offset(row);
+
+                        self.view.anchor = Some(line_start);
+                        if row + 1 < self.buffer.line_count() {
+                            self.set_cursor_offset(self.buffer.line_to_offset(row + 1));
+                        } else {
+                            self.set_cursor_offset(line_start + self.buffer.line_len(row));
+                        }
+                    }
+
+                    // Dragging from here selects
+                    _ => {
+                        self.view.anchor = Some(offset);
+                        self.set_cursor_offset(offset);
+                    }
+                }
+            }
+            MouseEventKind::Drag(MouseButton::Left) => {
+                if let Some(offset) = self.offset_at(event.column, event.row) {
+                    self.set_cursor_offset(offset);
+                }
+            }
+            MouseEventKind::ScrollUp => self.scroll_view(-3),
+            MouseEventKind::ScrollDown => self.scroll_view(3),
+            _ => {}
+        }
+
+        self.view.cursor_col_offset = self.get_line_number_width() + 1;
+        self.scroll_to_cursor();
     }
 
-    fn render_toolbar(&self, out: &mut Stdout) -> Result<(), std::io::Error> {
-        let (width, height) = terminal::size()?;
+    pub fn tick(&mut self) {
+        if self.message.as_ref().is_some_and(Message::is_expired) {
+            self.message = None;
+        }
 
-        let saved_text = if self.saved { "" } else { "Not Saved!" };
+        if self.disk_change.is_none()
+            && self.recovery.is_none()
+            && self.disk_checked.elapsed() >= Duration::from_secs(2)
+        {
+            self.disk_checked = Instant::now();
+            self.disk_change = self.read_disk_change();
+        }
 
-        let path_text = self.path.to_string_lossy().to_string();
-        let path_text_col = ((width as usize / 2) - (path_text.len() / 2)) as u16;
+        if !self.saved && self.recovery.is_none() && self.swap.is_due() {
+            let contents = self.format.encode(&self.buffer.to_string());
 
-        let position_text = format!("{}, {}", self.cursor_col, self.cursor_row);
-        let position_text_col = width - 1 - position_text.len() as u16;
+            if let Err(error) = self.swap.write(&contents) {
+                self.set_message(
+                    MessageLevel::Error,
+                    format!("Could not write {}: {}", self.swap.path().display(), error),
+                );
+            }
+        }
+    }
+
+    // Reads the file again if something else changed it since it was last
+    // opened or saved
+    fn read_disk_change(&mut self) -> Option<String> {
+        let metadata = fs::metadata(&self.path).ok()?;
 
-        execute!(
-            out,
-            cursor::MoveTo(0, height - 1),
-            SetBackgroundColor(Color::White),
-            SetForegroundColor(Color::Black),
-            Clear(terminal::ClearType::CurrentLine),
-            cursor::MoveTo(1, height - 1),
-            Print(saved_text),
-            cursor::MoveTo(path_text_col, height - 1),
-            Print(path_text),
-            cursor::MoveTo(position_text_col, height - 1),
-            Print(position_text)
-        )?;
+        if self
+            .disk_state
+            .is_some_and(|state| state.metadata_matches(&metadata))
+        {
+            return None;
+        }
+
+        let contents = fs::read_to_string(&self.path).ok()?;
 
-        Ok(())
+        match self.disk_state {
+            // Only the modification time changed
+            Some(state) if state.contents_match(&contents) => {
+                self.disk_state = Some(DiskState::new(&metadata, &contents));
+                None
+            }
+            _ => Some(contents),
+        }
     }
 
-    fn save(&mut self) {
-        let mut file = File::create(self.path.clone()).unwrap();
+    fn handle_disk_change_key(&mut self, event: KeyEvent) {
+        match event.code {
+            KeyCode::Char('r') => self.reload(),
+            KeyCode::Char('o') => {
+                self.disk_change = None;
+                self.write_and_report();
+            }
+            KeyCode::Char('k') | KeyCode::Esc => {
+                // Remember this version so it isn't asked about again
+                if let (Some(contents), Ok(metadata)) =
+                    (self.disk_change.take(), fs::metadata(&self.path))
+                {
+                    self.disk_state = Some(DiskState::new(&metadata, &contents));
+                }
 
-        for line in &self.lines {
-            writeln!(file, "{}", line).unwrap();
+                self.saved = false;
+            }
+            _ => {}
         }
+    }
+
+    // Replaces the buffer with what's on disk as an undoable edit, keeping
+    // the cursor where it was
+    fn reload(&mut self) {
+        let Some(contents) = self.disk_change.take() else {
+            return;
+        };
+        let Ok(metadata) = fs::metadata(&self.path) else {
+            return;
+        };
+
+        let (format, text) = FileFormat::detect(&contents);
+        let (row, col) = (self.view.cursor_row, self.view.cursor_col);
+        let len = self.buffer.to_string().len();
 
+        self.edit(0..len, &text, EditKind::Other);
+        self.format = format;
+        self.disk_state = Some(DiskState::new(&metadata, &contents));
         self.saved = true;
+        self.swap.remove();
+
+        self.view.cursor_row = row.min(self.buffer.line_count() - 1);
+        self.view.cursor_col = col.min(unicode::grapheme_count(
+            &self.buffer.line(self.view.cursor_row),
+        ));
+        self.set_message(MessageLevel::Info, "Reloaded from disk");
     }
 
-    fn move_cursor(&mut self, direction: Direction) {
-        match direction {
-            Direction::Up => self.cursor_row = self.cursor_row.saturating_sub(1),
-            Direction::Right => self.cursor_col = self.cursor_col.saturating_add(1),
-            Direction::Down => self.cursor_row = self.cursor_row.saturating_add(1),
-            Direction::Left => self.cursor_col = self.cursor_col.saturating_sub(1),
-            Direction::Front => self.cursor_col = 0,
-            Direction::Back => self.cursor_col = self.lines[self.cursor_row].len(),
+    fn handle_recovery_key(&mut self, event: KeyEvent) {
+        let Some(recovery) = &mut self.recovery else {
+            return;
+        };
+
+        match event.code {
+            KeyCode::Char('r') => {
+                let Some(recovery) = self.recovery.take() else {
+                    return;
+                };
+                let len = self.buffer.to_string().len();
+
+                self.format = recovery.format;
+                self.edit(0..len, &recovery.text, EditKind::Other);
+                self.set_cursor_offset(0);
+                self.set_message(MessageLevel::Info, "Recovered unsaved changes");
+            }
+            KeyCode::Char('d') if recovery.diff.is_some() => recovery.diff = None,
+            KeyCode::Char('d') => {
+                let diff = swap::diff(&self.buffer.to_string(), &recovery.text);
+
+                // Start at the first change
+                recovery.scroll = diff
+                    .iter()
+                    .position(|(kind, _)| *kind != DiffKind::Same)
+                    .unwrap_or(0)
+                    .saturating_sub(3);
+                recovery.diff = Some(diff);
+            }
+            KeyCode::Char('x') => {
+                self.recovery = None;
+                self.swap.remove();
+                self.set_message(MessageLevel::Info, "Discarded unsaved changes");
+            }
+            KeyCode::Up => recovery.scroll = recovery.scroll.saturating_sub(1),
+            KeyCode::Down => recovery.scroll += 1,
+            KeyCode::PageUp => recovery.scroll = recovery.scroll.saturating_sub(self.view.height),
+            KeyCode::PageDown => recovery.scroll += self.view.height,
+            _ => {}
         }
 
-        if self.cursor_row >= self.lines.len() {
-            self.cursor_row = self.lines.len() - 1;
+        if let Some(Recovery {
+            diff: Some(diff),
+            scroll,
+            ..
+        }) = &mut self.recovery
+        {
+            *scroll = (*scroll).min(diff.len().saturating_sub(self.view.height));
         }
+    }
+
+    pub fn set_message(&mut self, level: MessageLevel, text: impl Into<String>) {
+        self.message = Some(Message::new(level, text.into()));
+    }
+
+    pub fn resize(&mut self, area: Rect) {
+        self.view.cursor_col_offset = self.get_line_number_width() + 1;
+
+        // The bottom row is taken by the toolbar
+        self.view.x = area.x;
+        self.view.y = area.y;
+        self.view.width = area.width.saturating_sub(self.view.cursor_col_offset) as usize;
+        self.view.height = area.height.saturating_sub(1) as usize;
+
+        self.scroll_to_cursor();
+    }
 
-        let current_line = &self.lines[self.cursor_row];
+    // Puts another window's view in place, pulling positions past the end
+    // back in since the text may have been replaced since it was taken
+    pub fn set_view(&mut self, view: View) -> View {
+        let view = std::mem::replace(&mut self.view, view);
+        let last_row = self.buffer.line_count() - 1;
+        let end = self.buffer.line_to_offset(last_row) + self.buffer.line_len(last_row);
 
-        if self.cursor_col > current_line.len() {
-            self.cursor_col = current_line.len();
+        self.view.cursor_row = self.view.cursor_row.min(last_row);
+        self.view.cursor_col = self.view.cursor_col.min(unicode::grapheme_count(
+            &self.buffer.line(self.view.cursor_row),
+        ));
+        if self.view.scroll_row > last_row {
+            self.view.scroll_row = last_row;
+            self.view.scroll_segment = 0;
         }
+        self.view.scroll_segment = self
+            .view
+            .scroll_segment
+            .min(self.line_segments(self.view.scroll_row).len() - 1);
+        self.view.anchor = self.view.anchor.map(|anchor| anchor.min(end));
+
+        view
     }
 
-    fn insert_new_line(&mut self) {
-        if self.cursor_col == 0 {
-            // Beginning of line
-            self.lines.insert(self.cursor_row, String::new());
-        } else if self.cursor_col == self.lines[self.cursor_row].len() {
-            // End of line
-            self.lines.insert(self.cursor_row + 1, String::new());
-        } else {
-            // Middle of line
-            let new_line = self.lines[self.cursor_row].split_off(self.cursor_col);
-            self.lines.insert(self.cursor_row + 1, new_line);
+    fn cursor_screen_position(&self) -> (u16, u16) {
+        let line = self.buffer.line(self.view.cursor_row);
+        let (segment, segment_start) = self.cursor_segment();
+        let cursor_byte = unicode::grapheme_to_byte(&line, self.view.cursor_col);
+
+        let col = unicode::line_width(&line[segment_start..cursor_byte], self.tab_width)
+            .saturating_sub(self.view.scroll_col);
+        let row = self.screen_rows_between(
+            (self.view.scroll_row, self.view.scroll_segment),
+            (self.view.cursor_row, segment),
+        );
+
+        (
+            u16::try_from(col)
+                .unwrap_or(u16::MAX)
+                .saturating_add(self.view.x + self.view.cursor_col_offset),
+            u16::try_from(row)
+                .unwrap_or(u16::MAX)
+                .saturating_add(self.view.y),
+        )
+    }
+
+    // Only the focused window shows the cursor and prompts
+    pub fn render(&self, screen: &mut Screen, focused: bool) {
+        if let Some(diff) = self
+            .recovery
+            .as_ref()
+            .and_then(|recovery| recovery.diff.as_ref().map(|diff| (diff, recovery.scroll)))
+        {
+            self.render_diff(screen, diff.0, diff.1);
+            self.render_toolbar(screen, focused);
+            return;
         }
 
-        self.cursor_row += 1;
-        self.cursor_col = 0;
+        let line_number_width = self.get_line_number_width();
+        let selection = self.selection();
+
+        let mut row = 0;
 
-        self.saved = false;
+        for line_index in self.view.scroll_row..self.buffer.line_count() {
+            let line = self.buffer.line(line_index);
+            let line_start = self.buffer.line_to_offset(line_index);
+            let segments = self.line_segments(line_index);
+
+            let (matches, current_match) = self.highlights(&line);
+            let style_at = |byte: usize| {
+                if selection
+                    .as_ref()
+                    .is_some_and(|selection| selection.contains(&(line_start + byte)))
+                {
+                    Style {
+                        reverse: true,
+                        ..Style::default()
+                    }
+                } else if current_match
+                    .as_ref()
+                    .is_some_and(|current| current.contains(&(line_start + byte)))
+                {
+                    Style {
+                        fg: Color::Black,
+                        bg: Color::Cyan,
+                        ..Style::default()
+                    }
+                } else if matches.iter().any(|range| range.contains(&byte)) {
+                    Style {
+                        fg: Color::Black,
+                        bg: Color::Yellow,
+                        ..Style::default()
+                    }
+                } else {
+                    Style::default()
+                }
+            };
+
+            let skip = if line_index == self.view.scroll_row {
+                self.view.scroll_segment
+            } else {
+                0
+            };
+
+            for (segment, &start) in segments.iter().enumerate().skip(skip) {
+                if row as usize >= self.view.height {
+                    break;
+                }
+
+                // Wrapped rows get a marker in the gutter instead of a number
+                if segment == 0 {
+                    screen.put_str(
+                        self.view.x,
+                        self.view.y + row,
+                        &format!(
+                            "{:width$}",
+                            line_index + 1,
+                            width = line_number_width as usize
+                        ),
+                        Style::fg(Color::Red),
+                    );
+                } else {
+                    screen.put_str(
+                        self.view.x,
+                        self.view.y + row,
+                        &format!("{:>width$}", "↪", width = line_number_width as usize),
+                        Style::fg(Color::DarkGrey),
+                    );
+                }
+
+                let end = segments.get(segment + 1).copied().unwrap_or(line.len());
+                self.render_line(
+                    screen,
+                    self.view.x + line_number_width + 1,
+                    self.view.y + row,
+                    &line[start..end],
+                    |byte| style_at(start + byte),
+                );
+
+                row += 1;
+            }
+
+            if row as usize >= self.view.height {
+                break;
+            }
+        }
+
+        // Prompts in the toolbar take the cursor over
+        if focused {
+            screen.set_cursor(Some(self.cursor_screen_position()));
+        }
+        self.render_toolbar(screen, focused);
     }
 
-    fn clear_line(&mut self) {
-        self.lines[self.cursor_row].clear();
-        self.cursor_col = 0;
+    // Shows how the recovered text differs from the file, instead of the buffer
+    fn render_diff(&self, screen: &mut Screen, diff: &[(DiffKind, String)], scroll: usize) {
+        for (row, (kind, line)) in diff.iter().skip(scroll).take(self.view.height).enumerate() {
+            let (prefix, style) = match kind {
+                DiffKind::Same => ("  ", Style::default()),
+                DiffKind::Removed => ("- ", Style::fg(Color::Red)),
+                DiffKind::Added => ("+ ", Style::fg(Color::Green)),
+            };
+
+            let row = self.view.y + row as u16;
+            let end = screen.put_str(self.view.x, row, prefix, style);
+            self.render_line(screen, end, row, line, |_| style);
+        }
     }
 
-    fn insert_char(&mut self, c: char) {
-        self.lines[self.cursor_row].insert(self.cursor_col, c);
-        self.cursor_col += 1;
+    // Matches within a line to highlight, and the current match as buffer
+    // offsets
+    fn highlights(&self, line: &str) -> (Vec<Range<usize>>, Option<Range<usize>>) {
+        if let Some(search) = &self.search {
+            return (search.find_all(line), search.current.clone());
+        }
 
-        self.saved = false;
+        if let Some(replace) = &self.replace {
+            if let (ReplaceStage::Confirm, Some(regex)) = (replace.stage, &replace.regex) {
+                let current = replace.current.as_ref().map(|(range, _)| range.clone());
+                return (regex.find_all(line), current);
+            }
+        }
+
+        (Vec::new(), None)
     }
 
-    fn erase_char(&mut self) {
-        if self.cursor_col > 0 {
-            self.lines[self.cursor_row].remove(self.cursor_col - 1);
-            self.cursor_col -= 1;
-        } else if self.cursor_col == 0 && self.cursor_row > 0 {
-            self.cursor_col = self.lines[self.cursor_row - 1].len();
+    // Draws the horizontally scrolled part of a line, styling each grapheme
+    // by its byte offset into the line
+    fn render_line(
+        &self,
+        screen: &mut Screen,
+        x: u16,
+        y: u16,
+        line: &str,
+        style_at: impl Fn(usize) -> Style,
+    ) {
+        let start = self.view.scroll_col;
+        let end = self.view.scroll_col + self.view.width;
+        let mut col = 0;
+
+        for (byte, grapheme) in unicode::grapheme_indices(line) {
+            let grapheme_width = unicode::column_width(grapheme, col, self.tab_width);
+            let grapheme_end = col + grapheme_width;
+            let style = style_at(byte);
+
+            // Tabs are drawn as spaces up to the next tab stop
+            let tab;
+            let grapheme = if grapheme == "\t" {
+                tab = " ".repeat(grapheme_width);
+                &tab
+            } else {
+                grapheme
+            };
+
+            if grapheme_end > end {
+                // Pad a wide grapheme cut off by the right edge
+                let visible_start = col.max(start);
+                screen.put_str(
+                    x + (visible_start - start) as u16,
+                    y,
+                    &" ".repeat(end.saturating_sub(visible_start)),
+                    style,
+                );
+                break;
+            } else if col >= start {
+                screen.put_str(x + (col - start) as u16, y, grapheme, style);
+            } else if grapheme_end > start {
+                // Pad a wide grapheme cut off by the left edge
+                screen.put_str(x, y, &" ".repeat(grapheme_end - start), style);
+            }
+
+            col = grapheme_end;
+        }
+    }
+
+    fn get_line_number_width(&self) -> u16 {
+        format!("{}", self.buffer.line_count()).len() as u16
+    }
+
+    fn render_toolbar(&self, screen: &mut Screen, focused: bool) {
+        let row = self.view.y + self.view.height as u16;
+        let style = Style {
+            fg: Color::Black,
+            bg: Color::White,
+            ..Style::default()
+        };
+
+        if !focused {
+            self.render_status(
+                screen,
+                row,
+                Style {
+                    fg: Color::White,
+                    bg: Color::DarkGrey,
+                    ..Style::default()
+                },
+            );
+            return;
+        }
+
+        if let Some(search) = &self.search {
+            self.render_search_prompt(screen, search, row, style);
+            return;
+        }
+
+        if let Some(replace) = &self.replace {
+            self.render_replace_prompt(screen, replace, row, style);
+            return;
+        }
+
+        if self.disk_change.is_some() {
+            self.render_prompt(
+                screen,
+                row,
+                MessageLevel::Warning.style(),
+                "The file changed on disk.",
+                None,
+                "R reload  O overwrite  K keep ",
+            );
+            return;
+        }
+
+        if let Some(recovery) = &self.recovery {
+            let prompt = if recovery.diff.is_some() {
+                "Changes from the swap file:"
+            } else {
+                "Recover unsaved changes from a crash?"
+            };
+
+            self.render_prompt(
+                screen,
+                row,
+                MessageLevel::Warning.style(),
+                prompt,
+                None,
+                "R recover  D diff  X discard ",
+            );
+            return;
+        }
+
+        self.render_status(screen, row, style);
+    }
+
+    // File format, path and cursor position, with any message on the left
+    fn render_status(&self, screen: &mut Screen, row: u16, style: Style) {
+        let (x, width) = (self.view.x, self.toolbar_width());
+        let saved_text = if self.saved { "" } else { "Not Saved!" };
+
+        let path_text = self.path.to_string_lossy().to_string();
+        let path_text_col = (width / 2).saturating_sub(unicode::width(&path_text) as u16 / 2);
+
+        let position_text = format!("{}, {}", self.view.cursor_col, self.view.cursor_row);
+        let position_text_col = width.saturating_sub(1 + position_text.len() as u16);
+
+        let format_text = self.format.describe();
+        let format_text_col = position_text_col.saturating_sub(3 + format_text.len() as u16);
 
-            let line = self.lines.remove(self.cursor_row);
-            self.lines[self.cursor_row - 1] += &line;
+        screen.fill(x, row, width, style);
+        screen.put_str(x + format_text_col, row, &format_text, style);
+        screen.put_str(x + path_text_col, row, &path_text, style);
+        screen.put_str(x + position_text_col, row, &position_text, style);
 
-            self.cursor_row -= 1;
+        match &self.message {
+            Some(message) => {
+                let message_style = message.level.style();
+                let end = screen.put_str(x, row, &format!(" {} ", message.text), message_style);
+
+                // Keep a gap between the message and the rest of the toolbar
+                screen.put_str(end, row, " ", style);
+            }
+            None => {
+                screen.put_str(x + 1, row, saved_text, style);
+            }
+        }
+    }
+
+    fn toolbar_width(&self) -> u16 {
+        self.view.cursor_col_offset + self.view.width as u16
+    }
+
+    fn render_search_prompt(&self, screen: &mut Screen, search: &Search, row: u16, style: Style) {
+        let failing = if search.current.is_none() && !search.query.is_empty() {
+            "Failing "
         } else {
-            // At col=0 row=0, so do nothing
+            ""
+        };
+        let case = if search.case_sensitive {
+            "case-sensitive "
+        } else {
+            ""
+        };
+        let prompt = format!(" {}{}search: ", failing, case);
+        let hint = "Alt+C: toggle case  Up/Down: previous/next ";
+
+        self.render_prompt(screen, row, style, &prompt, Some(&search.query), hint);
+    }
+
+    fn render_replace_prompt(
+        &self,
+        screen: &mut Screen,
+        replace: &Replace,
+        row: u16,
+        style: Style,
+    ) {
+        let options_hint = "Alt+R: regex  Alt+C: case  Alt+W: whole word ";
+
+        match replace.stage {
+            ReplaceStage::Pattern => {
+                let prompt = format!(" Replace{}: ", replace.options());
+                self.render_prompt(
+                    screen,
+                    row,
+                    style,
+                    &prompt,
+                    Some(&replace.pattern),
+                    options_hint,
+                );
+            }
+            ReplaceStage::Replacement => {
+                let prompt = format!(" Replace{} {} with: ", replace.options(), replace.pattern);
+                let hint = if replace.use_regex {
+                    "$1: group 1  $$: dollar sign "
+                } else {
+                    ""
+                };
+
+                self.render_prompt(
+                    screen,
+                    row,
+                    style,
+                    &prompt,
+                    Some(&replace.replacement),
+                    hint,
+                );
+            }
+            ReplaceStage::Confirm => {
+                let replacement = replace
+                    .current
+                    .as_ref()
+                    .map_or("", |(_, replacement)| replacement.as_str());
+                let prompt = format!(" Replace with {}? ", replacement);
+                let hint = "y: replace  n: skip  a: all  q: quit ";
+
+                self.render_prompt(screen, row, style, &prompt, None, hint);
+            }
+        }
+    }
+
+    // Draws a prompt over the toolbar, moving the cursor to the end of its
+    // input if it has one
+    fn render_prompt(
+        &self,
+        screen: &mut Screen,
+        row: u16,
+        style: Style,
+        prompt: &str,
+        input: Option<&str>,
+        hint: &str,
+    ) {
+        let (x, width) = (self.view.x, self.toolbar_width());
+
+        screen.fill(x, row, width, style);
+        screen.put_str(
+            x + width.saturating_sub(unicode::width(hint) as u16),
+            row,
+            hint,
+            style,
+        );
+
+        let prompt_end = screen.put_str(x, row, prompt, style);
+
+        if let Some(input) = input {
+            let input_end = screen.put_str(prompt_end, row, input, style);
+            screen.put_str(input_end, row, " ", style);
+
+            screen.set_cursor(Some((input_end, row)));
+        }
+    }
+
+    fn save(&mut self) {
+        // Ask before overwriting changes made by something else
+        if let Some(contents) = self.read_disk_change() {
+            self.disk_change = Some(contents);
             return;
         }
 
-        self.saved = false;
+        self.write_and_report();
+    }
+
+    fn write_and_report(&mut self) {
+        match self.write_file() {
+            Ok(()) => {
+                self.saved = true;
+                self.swap.remove();
+                self.disk_state = fs::read_to_string(&self.path)
+                    .and_then(|contents| Ok(DiskState::new(&fs::metadata(&self.path)?, &contents)))
+                    .ok();
+                self.set_message(
+                    MessageLevel::Info,
+                    format!("Saved {}", self.path.to_string_lossy()),
+                );
+            }
+            Err(error) => {
+                self.set_message(MessageLevel::Error, format!("Could not save: {}", error))
+            }
+        }
+    }
+
+    // Writes to a temporary file next to the original and renames it into
+    // place, so the original is never left half-written
+    fn write_file(&self) -> Result<(), io::Error> {
+        // Write through symlinks rather than replacing them
+        let path = fs::canonicalize(&self.path).unwrap_or_else(|_| self.path.clone());
+
+        let permissions = match fs::metadata(&path) {
+            Ok(metadata) if metadata.permissions().readonly() => {
+                return Err(io::Error::new(
+                    io::ErrorKind::PermissionDenied,
+                    "file is read-only",
+                ));
+            }
+            Ok(metadata) => Some(metadata.permissions()),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => None,
+            Err(error) => return Err(error),
+        };
+
+        let file_name = path
+            .file_name()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "path is not a file"))?;
+        let directory = path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+            .unwrap_or_else(|| Path::new("."));
+        let temp_path = directory.join(format!(
+            ".{}.{}.tmp",
+            file_name.to_string_lossy(),
+            process::id()
+        ));
+
+        let result = (|| {
+            let mut file = OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&temp_path)?;
+
+            file.write_all(self.format.encode(&self.buffer.to_string()).as_bytes())?;
+            file.sync_all()?;
+
+            if let Some(permissions) = permissions {
+                fs::set_permissions(&temp_path, permissions)?;
+            }
+
+            fs::rename(&temp_path, &path)
+        })();
+
+        if result.is_err() {
+            let _ = fs::remove_file(&temp_path);
+        }
+
+        result
+    }
+
+    fn toggle_line_ending(&mut self) {
+        self.format.line_ending = match self.format.line_ending {
+            LineEnding::Lf => LineEnding::CrLf,
+            LineEnding::CrLf => LineEnding::Lf,
+        };
+        self.saved = false;
+
+        self.set_message(
+            MessageLevel::Info,
+            format!("Line endings set to {}", self.format.line_ending.name()),
+        );
+    }
+
+    fn toggle_final_newline(&mut self) {
+        self.format.final_newline = !self.format.final_newline;
+        self.saved = false;
+
+        if self.format.final_newline {
+            self.set_message(MessageLevel::Info, "Final newline enabled");
+        } else {
+            self.set_message(MessageLevel::Info, "Final newline disabled");
+        }
+    }
+
+    fn toggle_bom(&mut self) {
+        self.format.bom = !self.format.bom;
+        self.saved = false;
+
+        if self.format.bom {
+            self.set_message(MessageLevel::Info, "Byte order mark enabled");
+        } else {
+            self.set_message(MessageLevel::Info, "Byte order mark disabled");
+        }
+    }
+
+    // Moves the cursor, extending the selection when shift is held or the
+    // mark is active
+    fn navigate(&mut self, direction: Direction, shift: bool) {
+        if shift && self.view.anchor.is_none() {
+            self.view.anchor = Some(self.cursor_offset());
+        } else if !shift && !self.view.mark_active {
+            self.view.anchor = None;
+        }
+
+        self.move_cursor(direction);
+    }
+
+    fn selection(&self) -> Option<Range<usize>> {
+        let anchor = self.view.anchor?;
+        let cursor = self.cursor_offset();
+
+        match anchor.cmp(&cursor) {
+            std::cmp::Ordering::Less => Some(anchor..cursor),
+            std::cmp::Ordering::Equal => None,
+            std::cmp::Ordering::Greater => Some(cursor..anchor),
+        }
+    }
+
+    fn clear_selection(&mut self) {
+        self.view.anchor = None;
+        self.view.mark_active = false;
+    }
+
+    fn toggle_mark(&mut self) {
+        if self.view.mark_active {
+            self.clear_selection();
+            self.set_message(MessageLevel::Info, "Mark deactivated");
+        } else {
+            self.view.anchor = Some(self.cursor_offset());
+            self.view.mark_active = true;
+            self.set_message(MessageLevel::Info, "Mark set");
+        }
+    }
+
+    fn move_cursor(&mut self, direction: Direction) {
+        self.history.seal();
+
+        let (current_segment, segment_start) = self.cursor_segment();
+        let desired_col = self.view.desired_col.take().unwrap_or_else(|| {
+            let line = self.buffer.line(self.view.cursor_row);
+            let cursor_byte = unicode::grapheme_to_byte(&line, self.view.cursor_col);
+            unicode::line_width(&line[segment_start..cursor_byte], self.tab_width)
+        });
+
+        // Screen row within the line to move onto when moving vertically
+        let mut segment = 0;
+
+        match direction {
+            // Move by screen row through wrapped lines
+            Direction::Up if current_segment > 0 => segment = current_segment - 1,
+            Direction::Up if self.soft_wrap && self.view.cursor_row > 0 => {
+                self.view.cursor_row -= 1;
+                segment = usize::MAX;
+            }
+            Direction::Down
+                if current_segment + 1 < self.line_segments(self.view.cursor_row).len() =>
+            {
+                segment = current_segment + 1
+            }
+            Direction::Down
+                if self.soft_wrap && self.view.cursor_row + 1 >= self.buffer.line_count() =>
+            {
+                segment = current_segment
+            }
+            Direction::Up => self.view.cursor_row = self.view.cursor_row.saturating_sub(1),
+            Direction::Right => self.view.cursor_col = self.view.cursor_col.saturating_add(1),
+            Direction::Down => self.view.cursor_row = self.view.cursor_row.saturating_add(1),
+            Direction::Left => self.view.cursor_col = self.view.cursor_col.saturating_sub(1),
+            Direction::Front => self.view.cursor_col = 0,
+            Direction::Back => self.view.cursor_col = usize::MAX,
+            Direction::SmartFront => {
+                // Toggle between the first non-blank character and column 0
+                let line = self.buffer.line(self.view.cursor_row);
+                let indent = line.len() - line.trim_start().len();
+                let indent_col = unicode::byte_to_grapheme(&line, indent);
+
+                self.view.cursor_col = if self.view.cursor_col == indent_col {
+                    0
+                } else {
+                    indent_col
+                };
+            }
+            Direction::WordLeft => {
+                let offset = self.cursor_offset();
+                self.set_cursor_offset(self.previous_word_start(offset));
+            }
+            Direction::WordRight => {
+                let offset = self.cursor_offset();
+                self.set_cursor_offset(self.next_word_end(offset));
+            }
+            Direction::ParagraphUp => {
+                // Stop at the blank line before the previous paragraph
+                let mut row = self.view.cursor_row;
+                while row > 0 && self.is_blank_line(row) {
+                    row -= 1;
+                }
+                while row > 0 && !self.is_blank_line(row) {
+                    row -= 1;
+                }
+
+                self.view.cursor_row = row;
+                self.view.cursor_col = 0;
+            }
+            Direction::ParagraphDown => {
+                // Stop at the blank line after the next paragraph
+                let line_count = self.buffer.line_count();
+                let mut row = self.view.cursor_row;
+                while row < line_count && self.is_blank_line(row) {
+                    row += 1;
+                }
+                while row < line_count && !self.is_blank_line(row) {
+                    row += 1;
+                }
+
+                self.view.cursor_row = row;
+                self.view.cursor_col = if row < line_count { 0 } else { usize::MAX };
+            }
+            Direction::MatchingBracket => {
+                let offset = self.cursor_offset();
+                let text = self.buffer.to_string();
+
+                // Also look just behind the cursor, for after typing a bracket
+                let bracket = motion::matching_bracket(&text, offset).or_else(|| {
+                    let before = text[..offset].char_indices().next_back()?.0;
+                    motion::matching_bracket(&text, before)
+                });
+
+                match bracket {
+                    Some(bracket) => self.set_cursor_offset(bracket),
+                    None => self.set_message(MessageLevel::Info, "No matching bracket"),
+                }
+            }
+            Direction::PageUp => {
+                self.view.scroll_segment = 0;
+                self.view.cursor_row = self.view.cursor_row.saturating_sub(self.view.height);
+                self.view.scroll_row = self.view.scroll_row.saturating_sub(self.view.height);
+            }
+            Direction::PageDown => {
+                self.view.scroll_segment = 0;
+                self.view.cursor_row = self.view.cursor_row.saturating_add(self.view.height);
+                self.view.scroll_row = (self.view.scroll_row + self.view.height)
+                    .min(self.buffer.line_count().saturating_sub(self.view.height));
+            }
+            Direction::Top => {
+                self.view.cursor_row = 0;
+                self.view.cursor_col = 0;
+            }
+            Direction::Bottom => {
+                self.view.cursor_row = usize::MAX;
+                self.view.cursor_col = usize::MAX;
+            }
+        }
+
+        if self.view.cursor_row >= self.buffer.line_count() {
+            self.view.cursor_row = self.buffer.line_count() - 1;
+        }
+
+        if matches!(
+            direction,
+            Direction::Up | Direction::Down | Direction::PageUp | Direction::PageDown
+        ) {
+            self.view.cursor_col =
+                self.column_in_segment(self.view.cursor_row, segment, desired_col);
+            self.view.desired_col = Some(desired_col);
+        }
+
+        let current_line_len = unicode::grapheme_count(&self.buffer.line(self.view.cursor_row));
+
+        if self.view.cursor_col > current_line_len {
+            self.view.cursor_col = current_line_len;
+        }
+    }
+
+    fn is_blank_line(&self, row: usize) -> bool {
+        self.buffer.line(row).trim().is_empty()
+    }
+
+    fn scroll_to_cursor(&mut self) {
+        if self.soft_wrap {
+            self.scroll_to_cursor_segment();
+            return;
+        }
+
+        if self.view.cursor_row < self.view.scroll_row {
+            self.view.scroll_row = self.view.cursor_row;
+        } else if self.view.cursor_row >= self.view.scroll_row + self.view.height {
+            self.view.scroll_row = self.view.cursor_row + 1 - self.view.height.max(1);
+        }
+
+        let display_col = self.cursor_display_col();
+
+        if display_col < self.view.scroll_col {
+            self.view.scroll_col = display_col;
+        } else if display_col >= self.view.scroll_col + self.view.width {
+            self.view.scroll_col = display_col + 1 - self.view.width.max(1);
+        }
+    }
+
+    // Scrolls by screen rows so that the cursor's row of a wrapped line is
+    // in view
+    fn scroll_to_cursor_segment(&mut self) {
+        let (segment, _) = self.cursor_segment();
+        self.view.scroll_col = 0;
+
+        if (self.view.cursor_row, segment) < (self.view.scroll_row, self.view.scroll_segment) {
+            self.view.scroll_row = self.view.cursor_row;
+            self.view.scroll_segment = segment;
+            return;
+        }
+
+        let mut rows = self.screen_rows_between(
+            (self.view.scroll_row, self.view.scroll_segment),
+            (self.view.cursor_row, segment),
+        );
+
+        while rows >= self.view.height.max(1) {
+            if self.view.scroll_segment + 1 < self.line_segments(self.view.scroll_row).len() {
+                self.view.scroll_segment += 1;
+            } else {
+                self.view.scroll_row += 1;
+                self.view.scroll_segment = 0;
+            }
+
+            rows -= 1;
+        }
+    }
+
+    // Byte offsets where each screen row of a line starts, which is just the
+    // one row unless soft wrapping
+    fn line_segments(&self, row: usize) -> Vec<usize> {
+        if self.soft_wrap {
+            wrap::wrap_line(&self.buffer.line(row), self.view.width, self.tab_width)
+        } else {
+            vec![0]
+        }
+    }
+
+    // Index and start byte of the screen row the cursor is on within its line
+    fn cursor_segment(&self) -> (usize, usize) {
+        let line = self.buffer.line(self.view.cursor_row);
+        let cursor_byte = unicode::grapheme_to_byte(&line, self.view.cursor_col);
+        let segments = self.line_segments(self.view.cursor_row);
+        let segment = segments
+            .iter()
+            .rposition(|&start| start <= cursor_byte)
+            .unwrap_or(0);
+
+        (segment, segments[segment])
+    }
+
+    // Grapheme column shown at a display column of one of a line's screen rows
+    fn column_in_segment(&self, row: usize, segment: usize, display_col: usize) -> usize {
+        let line = self.buffer.line(row);
+        let segments = self.line_segments(row);
+        let segment = segment.min(segments.len() - 1);
+        let start = segments[segment];
+        let end = segments.get(segment + 1).copied().unwrap_or(line.len());
+
+        let mut col = unicode::column_to_grapheme(&line[start..end], display_col, self.tab_width);
+        if segment + 1 < segments.len() {
+            // Stay on this screen row instead of the start of the next one
+            col = col.min(unicode::grapheme_count(&line[start..end]).saturating_sub(1));
+        }
+
+        unicode::byte_to_grapheme(&line, start) + col
+    }
+
+    // Buffer offset shown at a position on the screen, with clicks in the
+    // gutter going to the start of the line
+    fn offset_at(&self, column: u16, row: u16) -> Option<usize> {
+        let row = row.checked_sub(self.view.y)?;
+        if row as usize >= self.view.height {
+            return None;
+        }
+
+        let (mut line, mut segment) = (self.view.scroll_row, self.view.scroll_segment);
+        for _ in 0..row {
+            if segment + 1 < self.line_segments(line).len() {
+                segment += 1;
+            } else if line + 1 < self.buffer.line_count() {
+                line += 1;
+                segment = 0;
+            }
+        }
+
+        let display_col = (column.saturating_sub(self.view.x + self.view.cursor_col_offset)
+            as usize)
+            + self.view.scroll_col;
+        let col = self.column_in_segment(line, segment, display_col);
+        let text = self.buffer.line(line);
+
+        Some(self.buffer.line_to_offset(line) + unicode::grapheme_to_byte(&text, col))
+    }
+
+    // Scrolls the view by screen rows, dragging the cursor along when it
+    // would go out of view
+    fn scroll_view(&mut self, rows: isize) {
+        for _ in 0..rows.unsigned_abs() {
+            if rows > 0 {
+                if self.view.scroll_segment + 1 < self.line_segments(self.view.scroll_row).len() {
+                    self.view.scroll_segment += 1;
+                } else if self.view.scroll_row + 1 < self.buffer.line_count() {
+                    self.view.scroll_row += 1;
+                    self.view.scroll_segment = 0;
+                }
+            } else if self.view.scroll_segment > 0 {
+                self.view.scroll_segment -= 1;
+            } else if self.view.scroll_row > 0 {
+                self.view.scroll_row -= 1;
+                self.view.scroll_segment = self.line_segments(self.view.scroll_row).len() - 1;
+            }
+        }
+
+        let (segment, _) = self.cursor_segment();
+        let (column, _) = self.cursor_screen_position();
+        let row =
+            if (self.view.cursor_row, segment) < (self.view.scroll_row, self.view.scroll_segment) {
+                Some(0)
+            } else {
+                let rows = self.screen_rows_between(
+                    (self.view.scroll_row, self.view.scroll_segment),
+                    (self.view.cursor_row, segment),
+                );
+                (rows >= self.view.height).then(|| self.view.height.saturating_sub(1))
+            };
+
+        if let Some(offset) = row.and_then(|row| self.offset_at(column, self.view.y + row as u16)) {
+            self.set_cursor_offset(offset);
+        }
+    }
+
+    // Number of screen rows from one (line, segment) position down to another
+    fn screen_rows_between(&self, from: (usize, usize), to: (usize, usize)) -> usize {
+        if to.0 < from.0 {
+            return 0;
+        }
+
+        let rows: usize = (from.0..to.0)
+            .map(|row| self.line_segments(row).len())
+            .sum();
+
+        (rows + to.1).saturating_sub(from.1)
+    }
+
+    fn toggle_soft_wrap(&mut self) {
+        self.soft_wrap = !self.soft_wrap;
+        self.view.scroll_col = 0;
+        self.view.scroll_segment = 0;
+
+        if self.soft_wrap {
+            self.set_message(MessageLevel::Info, "Soft wrap on");
+        } else {
+            self.set_message(MessageLevel::Info, "Soft wrap off");
+        }
+    }
+
+    pub fn cursor_display_col(&self) -> usize {
+        let line = self.buffer.line(self.view.cursor_row);
+
+        unicode::line_width(
+            &line[..unicode::grapheme_to_byte(&line, self.view.cursor_col)],
+            self.tab_width,
+        )
+    }
+
+    fn cursor_offset(&self) -> usize {
+        let line = self.buffer.line(self.view.cursor_row);
+
+        self.buffer.line_to_offset(self.view.cursor_row)
+            + unicode::grapheme_to_byte(&line, self.view.cursor_col)
+    }
+
+    fn set_cursor_offset(&mut self, offset: usize) {
+        self.view.cursor_row = self.buffer.offset_to_line(offset);
+
+        let line_start = self.buffer.line_to_offset(self.view.cursor_row);
+        let line = self.buffer.line(self.view.cursor_row);
+
+        self.view.cursor_col = unicode::byte_to_grapheme(&line, offset - line_start);
+        self.view.desired_col = None;
+    }
+
+    fn edit(&mut self, range: Range<usize>, text: &str, kind: EditKind) {
+        let cursor_before = self.cursor_offset();
+        let deleted = self.buffer.slice(range.clone());
+
+        self.replace(range.clone(), text);
+
+        let cursor_after = range.start + text.len();
+        self.set_cursor_offset(cursor_after);
+        self.clear_selection();
+
+        self.history.record(
+            Edit {
+                offset: range.start,
+                deleted,
+                inserted: text.to_string(),
+            },
+            kind,
+            cursor_before,
+            cursor_after,
+        );
+
+        self.saved = false;
+    }
+
+    // Changes the text, moving the other windows' views along with the text
+    // around them
+    fn replace(&mut self, range: Range<usize>, text: &str) {
+        let shift = |offset: usize| {
+            if offset <= range.start {
+                offset
+            } else if offset >= range.end {
+                offset - range.len() + text.len()
+            } else {
+                range.start
+            }
+        };
+
+        let offsets: Vec<(usize, usize)> = self
+            .other_views
+            .iter()
+            .map(|view| {
+                let row = view.cursor_row.min(self.buffer.line_count() - 1);
+                let line = self.buffer.line(row);
+                let cursor = self.buffer.line_to_offset(row)
+                    + unicode::grapheme_to_byte(&line, view.cursor_col);
+                let scroll = self
+                    .buffer
+                    .line_to_offset(view.scroll_row.min(self.buffer.line_count() - 1));
+
+                (shift(cursor), shift(scroll))
+            })
+            .collect();
+
+        self.buffer.delete(range.clone());
+        self.buffer.insert(range.start, text);
+
+        for (view, (cursor, scroll)) in self.other_views.iter_mut().zip(offsets) {
+            let row = self.buffer.offset_to_line(cursor);
+            let line_start = self.buffer.line_to_offset(row);
+
+            view.cursor_row = row;
+            view.cursor_col =
+                unicode::byte_to_grapheme(&self.buffer.line(row), cursor - line_start);
+            view.scroll_row = self.buffer.offset_to_line(scroll);
+            view.anchor = view.anchor.map(shift);
+        }
+    }
+
+    fn start_search(&mut self) {
+        let case_sensitive = self
+            .last_search
+            .as_ref()
+            .is_some_and(|search| search.case_sensitive);
+
+        self.history.seal();
+        self.search = Some(Search::new(self.cursor_offset(), case_sensitive));
+    }
+
+    fn handle_search_key(&mut self, event: KeyEvent) {
+        let Some(search) = &mut self.search else {
+            return;
+        };
+
+        match event.code {
+            // Cancel and go back to where the search started
+            KeyCode::Esc => {
+                let origin = search.origin;
+                self.search = None;
+                self.set_cursor_offset(origin);
+            }
+
+            // Accept the current match
+            KeyCode::Enter => self.last_search = self.search.take(),
+
+            // Toggle case sensitivity
+            KeyCode::Char('c') if event.modifiers.contains(KeyModifiers::ALT) => {
+                search.case_sensitive = !search.case_sensitive;
+                self.update_search();
+            }
+
+            // Next match, or repeat the last search if nothing was typed yet
+            KeyCode::Char('f') if event.modifiers.contains(KeyModifiers::CONTROL) => {
+                if search.query.is_empty() {
+                    if let Some(last_search) = &self.last_search {
+                        search.query = last_search.query.clone();
+                        self.update_search();
+                        return;
+                    }
+                }
+
+                self.search_next();
+            }
+            KeyCode::Down => self.search_next(),
+
+            // Previous match
+            KeyCode::Up => self.search_previous(),
+
+            // Edit the query
+            KeyCode::Backspace => {
+                search.query.pop();
+                self.update_search();
+            }
+            KeyCode::Char(c)
+                if !event
+                    .modifiers
+                    .intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) =>
+            {
+                search.query.push(c);
+                self.update_search();
+            }
+
+            _ => {}
+        }
+    }
+
+    // Searches again from where the search started after the query changed
+    fn update_search(&mut self) {
+        let Some(search) = &self.search else {
+            return;
+        };
+
+        let current = search.find_forward(&self.buffer.to_string(), search.origin);
+        self.set_search_match(current);
+    }
+
+    fn search_next(&mut self) {
+        let Some(search) = &self.search else {
+            return;
+        };
+
+        let from = search
+            .current
+            .as_ref()
+            .map_or(search.origin, |current| current.start + 1);
+        let current = search.find_forward(&self.buffer.to_string(), from);
+        self.set_search_match(current);
+    }
+
+    fn search_previous(&mut self) {
+        let Some(search) = &self.search else {
+            return;
+        };
+
+        let before = search
+            .current
+            .as_ref()
+            .map_or(search.origin, |current| current.start);
+        let current = search.find_backward(&self.buffer.to_string(), before);
+        self.set_search_match(current);
+    }
+
+    fn set_search_match(&mut self, current: Option<Range<usize>>) {
+        let Some(search) = &mut self.search else {
+            return;
+        };
+
+        let cursor = current
+            .as_ref()
+            .map_or(search.origin, |current| current.start);
+        search.current = current;
+
+        self.set_cursor_offset(cursor);
+    }
+
+    fn start_replace(&mut self) {
+        self.history.seal();
+        self.replace = Some(Replace::default());
+    }
+
+    fn handle_replace_key(&mut self, event: KeyEvent) {
+        let Some(replace) = &mut self.replace else {
+            return;
+        };
+
+        if replace.stage == ReplaceStage::Confirm {
+            match event.code {
+                KeyCode::Char('y' | ' ') => self.replace_current(),
+                KeyCode::Char('n') | KeyCode::Delete | KeyCode::Backspace => self.skip_current(),
+                KeyCode::Char('a' | '!') => {
+                    while self
+                        .replace
+                        .as_ref()
+                        .is_some_and(|replace| replace.current.is_some())
+                    {
+                        self.replace_current();
+                    }
+                }
+                KeyCode::Char('q') | KeyCode::Esc | KeyCode::Enter => self.finish_replace(),
+                _ => {}
+            }
+
+            return;
+        }
+
+        let input = match replace.stage {
+            ReplaceStage::Pattern => &mut replace.pattern,
+            _ => &mut replace.replacement,
+        };
+
+        match event.code {
+            KeyCode::Esc => self.replace = None,
+
+            KeyCode::Enter if replace.stage == ReplaceStage::Pattern => {
+                if replace.pattern.is_empty() {
+                    self.replace = None;
+                } else if let Err(error) = replace.compile() {
+                    self.set_message(MessageLevel::Error, format!("Invalid regex: {}", error));
+                } else {
+                    replace.stage = ReplaceStage::Replacement;
+                }
+            }
+            KeyCode::Enter => {
+                replace.stage = ReplaceStage::Confirm;
+                self.history.begin_group();
+
+                let from = self.cursor_offset();
+                if let Some(replace) = &mut self.replace {
+                    replace.origin = from;
+                }
+                self.find_replace_match(Some(from));
+            }
+
+            // Options, which are fixed once the pattern is compiled
+            KeyCode::Char('r')
+                if event.modifiers.contains(KeyModifiers::ALT)
+                    && replace.stage == ReplaceStage::Pattern =>
+            {
+                replace.use_regex = !replace.use_regex;
+            }
+            KeyCode::Char('c')
+                if event.modifiers.contains(KeyModifiers::ALT)
+                    && replace.stage == ReplaceStage::Pattern =>
+            {
+                replace.case_sensitive = !replace.case_sensitive;
+            }
+            KeyCode::Char('w')
+                if event.modifiers.contains(KeyModifiers::ALT)
+                    && replace.stage == ReplaceStage::Pattern =>
+            {
+                replace.whole_word = !replace.whole_word;
+            }
+
+            // Edit the pattern or replacement
+            KeyCode::Backspace => {
+                input.pop();
+            }
+            KeyCode::Char(c)
+                if !event
+                    .modifiers
+                    .intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) =>
+            {
+                input.push(c);
+            }
+
+            _ => {}
+        }
+    }
+
+    // Finds the next match at or after `from` through to the end of the
+    // buffer, then wraps around to the start and goes up to where the replace
+    // started, finishing the replace once there are none left
+    fn find_replace_match(&mut self, from: Option<usize>) {
+        let Some(replace) = &self.replace else {
+            return;
+        };
+        let (origin, wrapped) = (replace.origin, replace.wrapped);
+
+        let mut current =
+            from.and_then(|from| self.next_replace_match(from, wrapped.then_some(origin)));
+
+        if current.is_none() && !wrapped {
+            if let Some(replace) = &mut self.replace {
+                replace.wrapped = true;
+            }
+            current = self.next_replace_match(0, Some(origin));
+        }
+
+        match current {
+            Some((range, replacement)) => {
+                self.set_cursor_offset(range.start);
+                if let Some(replace) = &mut self.replace {
+                    replace.current = Some((range, replacement));
+                }
+            }
+            None => self.finish_replace(),
+        }
+    }
+
+    // The first match at or after `from` that starts before `before`, with
+    // its replacement
+    fn next_replace_match(
+        &self,
+        from: usize,
+        before: Option<usize>,
+    ) -> Option<(Range<usize>, String)> {
+        let replace = self.replace.as_ref()?;
+        let regex = replace.regex.as_ref()?;
+
+        let mut line_index = self.buffer.offset_to_line(from);
+        let mut start = from - self.buffer.line_to_offset(line_index);
+
+        while line_index < self.buffer.line_count() {
+            let line = self.buffer.line(line_index);
+            let line_start = self.buffer.line_to_offset(line_index);
+
+            if before.is_some_and(|before| line_start + start >= before) {
+                return None;
+            }
+
+            if let Some(captures) = regex.captures_at(&line, start) {
+                let range = captures.range();
+
+                if before.is_some_and(|before| line_start + range.start >= before) {
+                    return None;
+                }
+
+                return Some((
+                    line_start + range.start..line_start + range.end,
+                    replace.expand(&line, &captures),
+                ));
+            }
+
+            line_index += 1;
+            start = 0;
+        }
+
+        None
+    }
+
+    fn replace_current(&mut self) {
+        let Some((range, replacement)) = self
+            .replace
+            .as_mut()
+            .and_then(|replace| replace.current.take())
+        else {
+            return;
+        };
+
+        self.edit(range.clone(), &replacement, EditKind::Other);
+
+        if let Some(replace) = &mut self.replace {
+            replace.replaced += 1;
+
+            // Replacements after wrapping around move the text the replace
+            // started at
+            if replace.wrapped {
+                replace.origin = replace.origin - range.len() + replacement.len();
+            }
+        }
+
+        let end = range.start + replacement.len();
+        let from = if range.is_empty() {
+            self.next_char_offset(end)
+        } else {
+            Some(end)
+        };
+        self.find_replace_match(from);
+    }
+
+    fn skip_current(&mut self) {
+        let Some((range, _)) = self
+            .replace
+            .as_mut()
+            .and_then(|replace| replace.current.take())
+        else {
+            return;
+        };
+
+        let from = if range.is_empty() {
+            self.next_char_offset(range.end)
+        } else {
+            Some(range.end)
+        };
+        self.find_replace_match(from);
+    }
+
+    fn finish_replace(&mut self) {
+        let Some(replace) = self.replace.take() else {
+            return;
+        };
+
+        if replace.stage == ReplaceStage::Confirm {
+            self.history.end_group();
+        }
+
+        let plural = if replace.replaced == 1 { "" } else { "s" };
+        self.set_message(
+            MessageLevel::Info,
+            format!("Replaced {} occurrence{}", replace.replaced, plural),
+        );
+    }
+
+    // The offset just past the character at `offset`, including line breaks
+    fn next_char_offset(&self, offset: usize) -> Option<usize> {
+        let line_index = self.buffer.offset_to_line(offset);
+        let line = self.buffer.line(line_index);
+        let column = offset - self.buffer.line_to_offset(line_index);
+
+        match line[column..].chars().next() {
+            Some(c) => Some(offset + c.len_utf8()),
+            None if line_index + 1 < self.buffer.line_count() => Some(offset + 1),
+            None => None,
+        }
+    }
+
+    fn undo(&mut self) {
+        if let Some((replacements, cursor)) = self.history.undo() {
+            for (range, text) in replacements {
+                self.replace(range, &text);
+            }
+
+            self.clear_selection();
+            self.set_cursor_offset(cursor);
+            self.saved = false;
+        }
+    }
+
+    fn redo(&mut self) {
+        if let Some((replacements, cursor)) = self.history.redo() {
+            for (range, text) in replacements {
+                self.replace(range, &text);
+            }
+
+            self.clear_selection();
+            self.set_cursor_offset(cursor);
+            self.saved = false;
+        }
+    }
+
+    fn insert_new_line(&mut self) {
+        let offset = self.cursor_offset();
+        let range = self.selection().unwrap_or(offset..offset);
+
+        self.edit(range, "\n", EditKind::Other);
+    }
+
+    fn clear_line(&mut self, kill_ring: &mut KillRing, append: bool) {
+        let line_start = self.buffer.line_to_offset(self.view.cursor_row);
+        let line_len = self.buffer.line_len(self.view.cursor_row);
+
+        if line_len > 0 {
+            self.kill(kill_ring, line_start..line_start + line_len, append);
+        } else {
+            self.view.cursor_col = 0;
+        }
+    }
+
+    // Kills the rest of the line, or the line break when already at its end
+    fn kill_to_line_end(&mut self, kill_ring: &mut KillRing, append: bool) {
+        let offset = self.cursor_offset();
+        let line_end = self.buffer.line_to_offset(self.view.cursor_row)
+            + self.buffer.line_len(self.view.cursor_row);
+
+        if offset < line_end {
+            self.kill(kill_ring, offset..line_end, append);
+        } else if self.view.cursor_row + 1 < self.buffer.line_count() {
+            self.kill(kill_ring, offset..offset + 1, append);
+        }
+    }
+
+    // Words never span lines, so only the text back to the nearest line with
+    // something other than whitespace on it is looked at
+    fn previous_word_start(&self, offset: usize) -> usize {
+        let mut row = self.buffer.offset_to_line(offset);
+
+        loop {
+            let line_start = self.buffer.line_to_offset(row);
+            let text = self.buffer.slice(line_start..offset);
+
+            if row == 0 || !text.chars().all(char::is_whitespace) {
+                return line_start + motion::previous_word_start(&text, text.len());
+            }
+
+            row -= 1;
+        }
+    }
+
+    fn next_word_end(&self, offset: usize) -> usize {
+        let mut row = self.buffer.offset_to_line(offset);
+
+        loop {
+            let line_end = self.buffer.line_to_offset(row) + self.buffer.line_len(row);
+            let text = self.buffer.slice(offset..line_end);
+
+            if row + 1 == self.buffer.line_count() || !text.chars().all(char::is_whitespace) {
+                return offset + motion::next_word_end(&text, 0);
+            }
+
+            row += 1;
+        }
+    }
+
+    fn kill_word_backward(&mut self, kill_ring: &mut KillRing, append: bool) {
+        let offset = self.cursor_offset();
+        let start = self.previous_word_start(offset);
+
+        if start < offset {
+            self.kill(kill_ring, start..offset, append);
+        }
+    }
+
+    fn kill_word_forward(&mut self, kill_ring: &mut KillRing, append: bool) {
+        let offset = self.cursor_offset();
+        let end = self.next_word_end(offset);
+
+        if offset < end {
+            self.kill(kill_ring, offset..end, append);
+        }
+    }
+
+    // Deletes the text and pushes it onto the kill ring, adding to the newest
+    // entry when the previous command was a kill too
+    fn kill(&mut self, kill_ring: &mut KillRing, range: Range<usize>, append: bool) {
+        let text = self.buffer.slice(range.clone());
+
+        if append {
+            kill_ring.append(&text);
+        } else {
+            kill_ring.push(text);
+        }
+
+        self.edit(range, "", EditKind::Other);
+        kill_ring.killing = true;
+    }
+
+    // The current line and its line break, for cutting and copying whole lines
+    fn line_with_break(&self) -> (Range<usize>, String) {
+        let line_start = self.buffer.line_to_offset(self.view.cursor_row);
+        let line_end = line_start + self.buffer.line_len(self.view.cursor_row);
+        let text = format!("{}\n", self.buffer.slice(line_start..line_end));
+
+        if self.view.cursor_row + 1 < self.buffer.line_count() {
+            (line_start..line_end + 1, text)
+        } else {
+            (line_start.saturating_sub(1)..line_end, text)
+        }
+    }
+
+    fn copy(&mut self, kill_ring: &mut KillRing) {
+        if let Some(selection) = self.selection() {
+            kill_ring.push(self.buffer.slice(selection));
+            self.clear_selection();
+            self.set_message(MessageLevel::Info, "Copied selection");
+        } else {
+            let (_, text) = self.line_with_break();
+            kill_ring.push(text);
+            self.set_message(MessageLevel::Info, "Copied line");
+        }
+    }
+
+    fn cut(&mut self, kill_ring: &mut KillRing, append: bool) {
+        if let Some(selection) = self.selection() {
+            self.kill(kill_ring, selection, append);
+            return;
+        }
+
+        let (range, text) = self.line_with_break();
+
+        if append {
+            kill_ring.append(&text);
+        } else {
+            kill_ring.push(text);
+        }
+
+        self.edit(range, "", EditKind::Other);
+        self.view.cursor_col = 0;
+        kill_ring.killing = true;
+    }
+
+    fn yank(&mut self, kill_ring: &mut KillRing) {
+        let Some(text) = kill_ring.current().map(str::to_string) else {
+            self.set_message(MessageLevel::Info, "Nothing to paste");
+            return;
+        };
+
+        let offset = self.cursor_offset();
+        let range = self.selection().unwrap_or(offset..offset);
+        let start = range.start;
+
+        self.history.seal();
+        self.edit(range, &text, EditKind::Other);
+        kill_ring.yanked = Some(start..start + text.len());
+    }
+
+    // Replaces the text that was just yanked with the next older kill
+    fn yank_pop(&mut self, kill_ring: &mut KillRing, yanked: Option<Range<usize>>) {
+        let Some(yanked) = yanked else {
+            self.set_message(
+                MessageLevel::Warning,
+                "Alt+Y only works right after a paste",
+            );
+            return;
+        };
+
+        let Some(text) = kill_ring.rotate().map(str::to_string) else {
+            return;
+        };
+
+        let start = yanked.start;
+        self.edit(yanked, &text, EditKind::Other);
+        kill_ring.yanked = Some(start..start + text.len());
+    }
+
+    fn insert_char(&mut self, c: char) {
+        let offset = self.cursor_offset();
+        let range = self.selection().unwrap_or(offset..offset);
+
+        self.edit(range, c.encode_utf8(&mut [0; 4]), EditKind::Typing);
+    }
+
+    fn delete_char(&mut self) {
+        if let Some(selection) = self.selection() {
+            self.edit(selection, "", EditKind::Other);
+            return;
+        }
+
+        let offset = self.cursor_offset();
+        let line = self.buffer.line(self.view.cursor_row);
+
+        if self.view.cursor_col < unicode::grapheme_count(&line) {
+            let end = self.buffer.line_to_offset(self.view.cursor_row)
+                + unicode::grapheme_to_byte(&line, self.view.cursor_col + 1);
+
+            self.edit(offset..end, "", EditKind::Other);
+        } else if self.view.cursor_row + 1 < self.buffer.line_count() {
+            // Remove the line break joining the next line onto this one
+            self.edit(offset..offset + 1, "", EditKind::Other);
+        }
+    }
+
+    fn indent(&mut self) {
+        if self.selection().is_some() {
+            let indent = self.indent_unit();
+            self.map_lines(|line| format!("{}{}", indent, line));
+        } else if self.expand_tab {
+            // Fill up to the next tab stop
+            let offset = self.cursor_offset();
+            let spaces = self.tab_width - self.cursor_display_col() % self.tab_width;
+            self.edit(offset..offset, &" ".repeat(spaces), EditKind::Typing);
+        } else {
+            let offset = self.cursor_offset();
+            self.edit(offset..offset, "\t", EditKind::Typing);
+        }
+    }
+
+    fn dedent(&mut self) {
+        let tab_width = self.tab_width;
+
+        self.map_lines(|line| {
+            let indent = if line.starts_with('\t') {
+                1
+            } else {
+                line.len() - line.trim_start_matches(' ').len()
+            };
+
+            line[indent.min(tab_width)..].to_string()
+        });
+    }
+
+    fn indent_unit(&self) -> String {
+        if self.expand_tab {
+            " ".repeat(self.tab_width)
+        } else {
+            String::from("\t")
+        }
+    }
+
+    fn toggle_expand_tab(&mut self) {
+        self.expand_tab = !self.expand_tab;
+
+        if self.expand_tab {
+            self.set_message(MessageLevel::Info, "Indenting with spaces");
+        } else {
+            self.set_message(MessageLevel::Info, "Indenting with tabs");
+        }
+    }
+
+    // Rewrites the lines touched by the selection, or the current line, in a
+    // single edit
+    fn map_lines(&mut self, f: impl Fn(&str) -> String) {
+        let selection = self.selection();
+        let (first, last) = match &selection {
+            Some(selection) => {
+                let first = self.buffer.offset_to_line(selection.start);
+                let last = self.buffer.offset_to_line(selection.end);
+
+                // A selection ending at the start of a line doesn't include it
+                if last > first && self.buffer.line_to_offset(last) == selection.end {
+                    (first, last - 1)
+                } else {
+                    (first, last)
+                }
+            }
+            None => (self.view.cursor_row, self.view.cursor_row),
+        };
+
+        let start = self.buffer.line_to_offset(first);
+        let end = self.buffer.line_to_offset(last) + self.buffer.line_len(last);
+        let old_text = self.buffer.slice(start..end);
+        let new_text = old_text.split('\n').map(f).collect::<Vec<_>>().join("\n");
+
+        if new_text == old_text {
+            return;
+        }
+
+        let (row, col) = (self.view.cursor_row, self.view.cursor_col);
+        self.edit(start..end, &new_text, EditKind::Other);
+
+        if selection.is_some() {
+            self.view.anchor = Some(start);
+            self.set_cursor_offset(start + new_text.len());
+        } else {
+            self.view.cursor_row = row;
+            self.view.cursor_col = (col + unicode::grapheme_count(&new_text))
+                .saturating_sub(unicode::grapheme_count(&old_text));
+        }
+    }
+
+    fn duplicate_line(&mut self, below: bool) {
+        let line = self.buffer.line(self.view.cursor_row);
+        let line_start = self.buffer.line_to_offset(self.view.cursor_row);
+        let (row, col) = (self.view.cursor_row, self.view.cursor_col);
+
+        if below {
+            let line_end = line_start + line.len();
+            self.edit(line_end..line_end, &format!("\n{}", line), EditKind::Other);
+            self.view.cursor_row = row + 1;
+        } else {
+            self.edit(
+                line_start..line_start,
+                &format!("{}\n", line),
+                EditKind::Other,
+            );
+            self.view.cursor_row = row;
+        }
+
+        self.view.cursor_col = col;
+    }
+
+    fn move_line_up(&mut self) {
+        if self.view.cursor_row > 0 {
+            self.swap_lines(self.view.cursor_row - 1);
+            self.view.cursor_row -= 1;
+        }
+    }
+
+    fn move_line_down(&mut self) {
+        if self.view.cursor_row + 1 < self.buffer.line_count() {
+            self.swap_lines(self.view.cursor_row);
+            self.view.cursor_row += 1;
+        }
+    }
+
+    // Swaps line `row` with the one below it, keeping the cursor where it was
+    fn swap_lines(&mut self, row: usize) {
+        let start = self.buffer.line_to_offset(row);
+        let end = self.buffer.line_to_offset(row + 1) + self.buffer.line_len(row + 1);
+        let text = format!("{}\n{}", self.buffer.line(row + 1), self.buffer.line(row));
+        let (cursor_row, cursor_col) = (self.view.cursor_row, self.view.cursor_col);
+
+        self.edit(start..end, &text, EditKind::Other);
+        self.view.cursor_row = cursor_row;
+        self.view.cursor_col = cursor_col;
+    }
+
+    // Joins the next line onto this one, replacing its indentation with a
+    // single space
+    fn join_lines(&mut self) {
+        if self.view.cursor_row + 1 >= self.buffer.line_count() {
+            return;
+        }
+
+        let line = self.buffer.line(self.view.cursor_row);
+        let next_line = self.buffer.line(self.view.cursor_row + 1);
+        let next_trimmed = next_line.trim_start();
+
+        let start = self.buffer.line_to_offset(self.view.cursor_row) + line.len();
+        let end = start + 1 + next_line.len() - next_trimmed.len();
+        let separator = if line.trim().is_empty()
+            || line.ends_with(char::is_whitespace)
+            || next_trimmed.is_empty()
+        {
+            ""
+        } else {
+            " "
+        };
+
+        self.edit(start..end, separator, EditKind::Other);
+        self.set_cursor_offset(start);
+    }
+
+    fn erase_char(&mut self) {
+        if let Some(selection) = self.selection() {
+            self.edit(selection, "", EditKind::Other);
+            return;
+        }
+
+        let offset = self.cursor_offset();
+
+        if self.view.cursor_col > 0 {
+            let line = self.buffer.line(self.view.cursor_row);
+            let grapheme_len = offset
+                - self.buffer.line_to_offset(self.view.cursor_row)
+                - unicode::grapheme_to_byte(&line, self.view.cursor_col - 1);
+
+            self.edit(offset - grapheme_len..offset, "", EditKind::Other);
+        } else if self.view.cursor_row > 0 {
+            // Remove the line break joining this line onto the previous one
+            self.edit(offset - 1..offset, "", EditKind::Other);
+        }
     }
 }
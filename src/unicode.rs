@@ -0,0 +1,423 @@
+// A compact approximation of extended grapheme clustering (UAX #29) and
+// terminal display width (wcwidth), covering combining marks, emoji
+// sequences, regional indicator flags, Hangul syllables and East Asian wide
+// characters.
+
+const ZWJ: char = '\u{200D}';
+
+const EXTEND: &[(u32, u32)] = &[
+    (0x0300, 0x036F),
+    (0x0483, 0x0489),
+    (0x0591, 0x05BD),
+    (0x05BF, 0x05BF),
+    (0x05C1, 0x05C2),
+    (0x05C4, 0x05C5),
+    (0x05C7, 0x05C7),
+    (0x0610, 0x061A),
+    (0x064B, 0x065F),
+    (0x0670, 0x0670),
+    (0x06D6, 0x06DC),
+    (0x06DF, 0x06E4),
+    (0x06E7, 0x06E8),
+    (0x06EA, 0x06ED),
+    (0x0711, 0x0711),
+    (0x0730, 0x074A),
+    (0x07A6, 0x07B0),
+    (0x07EB, 0x07F3),
+    (0x0816, 0x082D),
+    (0x0859, 0x085B),
+    (0x08D3, 0x08E1),
+    (0x08E3, 0x0903),
+    (0x093A, 0x093C),
+    (0x093E, 0x094F),
+    (0x0951, 0x0957),
+    (0x0962, 0x0963),
+    (0x0981, 0x0983),
+    (0x09BC, 0x09BC),
+    (0x09BE, 0x09CD),
+    (0x09D7, 0x09D7),
+    (0x09E2, 0x09E3),
+    (0x0A01, 0x0A03),
+    (0x0A3C, 0x0A51),
+    (0x0A70, 0x0A71),
+    (0x0A75, 0x0A75),
+    (0x0A81, 0x0A83),
+    (0x0ABC, 0x0ACD),
+    (0x0B01, 0x0B03),
+    (0x0B3C, 0x0B57),
+    (0x0BBE, 0x0BCD),
+    (0x0C00, 0x0C04),
+    (0x0C3E, 0x0C56),
+    (0x0C81, 0x0C83),
+    (0x0CBC, 0x0CD6),
+    (0x0D00, 0x0D03),
+    (0x0D3B, 0x0D3C),
+    (0x0D3E, 0x0D4D),
+    (0x0D57, 0x0D57),
+    (0x0DCA, 0x0DDF),
+    (0x0E31, 0x0E31),
+    (0x0E34, 0x0E3A),
+    (0x0E47, 0x0E4E),
+    (0x0EB1, 0x0EB1),
+    (0x0EB4, 0x0EBC),
+    (0x0EC8, 0x0ECD),
+    (0x0F18, 0x0F19),
+    (0x0F35, 0x0F35),
+    (0x0F37, 0x0F37),
+    (0x0F39, 0x0F39),
+    (0x0F71, 0x0F84),
+    (0x0F86, 0x0F87),
+    (0x0F8D, 0x0FBC),
+    (0x102B, 0x103E),
+    (0x1AB0, 0x1AFF),
+    (0x1DC0, 0x1DFF),
+    (0x200C, 0x200D),
+    (0x20D0, 0x20FF),
+    (0x302A, 0x302F),
+    (0x3099, 0x309A),
+    (0xFE00, 0xFE0F),
+    (0xFE20, 0xFE2F),
+    (0x1F3FB, 0x1F3FF),
+    (0xE0020, 0xE007F),
+    (0xE0100, 0xE01EF),
+];
+
+const WIDE: &[(u32, u32)] = &[
+    (0x1100, 0x115F),
+    (0x231A, 0x231B),
+    (0x2329, 0x232A),
+    (0x23E9, 0x23EC),
+    (0x23F0, 0x23F0),
+    (0x23F3, 0x23F3),
+    (0x25FD, 0x25FE),
+    (0x2614, 0x2615),
+    (0x2648, 0x2653),
+    (0x267F, 0x267F),
+    (0x2693, 0x2693),
+    (0x26A1, 0x26A1),
+    (0x26AA, 0x26AB),
+    (0x26BD, 0x26BE),
+    (0x26C4, 0x26C5),
+    (0x26CE, 0x26CE),
+    (0x26D4, 0x26D4),
+    (0x26EA, 0x26EA),
+    (0x26F2, 0x26F3),
+    (0x26F5, 0x26F5),
+    (0x26FA, 0x26FA),
+    (0x26FD, 0x26FD),
+    (0x2705, 0x2705),
+    (0x270A, 0x270B),
+    (0x2728, 0x2728),
+    (0x274C, 0x274C),
+    (0x274E, 0x274E),
+    (0x2753, 0x2755),
+    (0x2757, 0x2757),
+    (0x2795, 0x2797),
+    (0x27B0, 0x27B0),
+    (0x27BF, 0x27BF),
+    (0x2B1B, 0x2B1C),
+    (0x2B50, 0x2B50),
+    (0x2B55, 0x2B55),
+    (0x2E80, 0x303E),
+    (0x3041, 0x33FF),
+    (0x3400, 0x4DBF),
+    (0x4E00, 0x9FFF),
+    (0xA000, 0xA4CF),
+    (0xA960, 0xA97F),
+    (0xAC00, 0xD7A3),
+    (0xF900, 0xFAFF),
+    (0xFE10, 0xFE19),
+    (0xFE30, 0xFE6F),
+    (0xFF00, 0xFF60),
+    (0xFFE0, 0xFFE6),
+    (0x16FE0, 0x16FE4),
+    (0x17000, 0x18AFF),
+    (0x1B000, 0x1B2FF),
+    (0x1F004, 0x1F004),
+    (0x1F0CF, 0x1F0CF),
+    (0x1F18E, 0x1F18E),
+    (0x1F191, 0x1F19A),
+    (0x1F200, 0x1F251),
+    (0x1F300, 0x1F64F),
+    (0x1F680, 0x1F6FF),
+    (0x1F7E0, 0x1F7EB),
+    (0x1F90C, 0x1F9FF),
+    (0x1FA70, 0x1FAFF),
+    (0x20000, 0x2FFFD),
+    (0x30000, 0x3FFFD),
+];
+
+fn in_table(table: &[(u32, u32)], c: char) -> bool {
+    let c = c as u32;
+
+    table
+        .binary_search_by(|&(start, end)| {
+            if end < c {
+                std::cmp::Ordering::Less
+            } else if start > c {
+                std::cmp::Ordering::Greater
+            } else {
+                std::cmp::Ordering::Equal
+            }
+        })
+        .is_ok()
+}
+
+fn is_extend(c: char) -> bool {
+    in_table(EXTEND, c)
+}
+
+fn is_regional_indicator(c: char) -> bool {
+    ('\u{1F1E6}'..='\u{1F1FF}').contains(&c)
+}
+
+fn is_pictographic(c: char) -> bool {
+    matches!(c as u32, 0x2190..=0x2BFF | 0x1F000..=0x1FAFF)
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Hangul {
+    L,
+    V,
+    T,
+    Lv,
+    Lvt,
+}
+
+fn hangul(c: char) -> Option<Hangul> {
+    match c as u32 {
+        0x1100..=0x115F | 0xA960..=0xA97C => Some(Hangul::L),
+        0x1160..=0x11A7 | 0xD7B0..=0xD7C6 => Some(Hangul::V),
+        0x11A8..=0x11FF | 0xD7CB..=0xD7FB => Some(Hangul::T),
+        syllable @ 0xAC00..=0xD7A3 if (syllable - 0xAC00) % 28 == 0 => Some(Hangul::Lv),
+        0xAC00..=0xD7A3 => Some(Hangul::Lvt),
+        _ => None,
+    }
+}
+
+fn joins_hangul(previous: char, c: char) -> bool {
+    use Hangul::*;
+
+    matches!(
+        (hangul(previous), hangul(c)),
+        (Some(L), Some(L | V | Lv | Lvt)) | (Some(Lv | V), Some(V | T)) | (Some(Lvt | T), Some(T))
+    )
+}
+
+pub struct GraphemeIndices<'a> {
+    text: &'a str,
+    offset: usize,
+}
+
+impl<'a> Iterator for GraphemeIndices<'a> {
+    type Item = (usize, &'a str);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let start = self.offset;
+        let mut chars = self.text[start..].char_indices();
+        let (_, first) = chars.next()?;
+
+        let mut end = first.len_utf8();
+        let mut previous = first;
+        let mut regional_indicators = usize::from(is_regional_indicator(first));
+
+        for (i, c) in chars {
+            let joins = if previous == '\r' {
+                c == '\n'
+            } else if previous.is_control() || c.is_control() {
+                false
+            } else if is_extend(c) {
+                true
+            } else if previous == ZWJ {
+                is_pictographic(c)
+            } else if is_regional_indicator(c) {
+                regional_indicators % 2 == 1
+            } else {
+                joins_hangul(previous, c)
+            };
+
+            if !joins {
+                break;
+            }
+
+            if is_regional_indicator(c) {
+                regional_indicators += 1;
+            }
+
+            end = i + c.len_utf8();
+            previous = c;
+        }
+
+        self.offset = start + end;
+        Some((start, &self.text[start..start + end]))
+    }
+}
+
+pub fn grapheme_indices(text: &str) -> GraphemeIndices<'_> {
+    GraphemeIndices { text, offset: 0 }
+}
+
+pub fn grapheme_count(text: &str) -> usize {
+    grapheme_indices(text).count()
+}
+
+// Byte offset of the grapheme at `index`, or the end of the text
+pub fn grapheme_to_byte(text: &str, index: usize) -> usize {
+    grapheme_indices(text)
+        .nth(index)
+        .map_or(text.len(), |(offset, _)| offset)
+}
+
+// Number of graphemes starting before `byte`, rounding up mid-grapheme
+pub fn byte_to_grapheme(text: &str, byte: usize) -> usize {
+    grapheme_indices(text)
+        .take_while(|&(offset, _)| offset < byte)
+        .count()
+}
+
+pub fn grapheme_width(grapheme: &str) -> usize {
+    let mut chars = grapheme.chars();
+    let Some(first) = chars.next() else {
+        return 0;
+    };
+
+    if is_regional_indicator(first) || in_table(WIDE, first) {
+        2
+    } else if is_extend(first) {
+        0
+    } else if chars.any(|c| c == '\u{FE0F}') {
+        // Emoji presentation selector
+        2
+    } else {
+        1
+    }
+}
+
+pub fn width(text: &str) -> usize {
+    grapheme_indices(text)
+        .map(|(_, grapheme)| grapheme_width(grapheme))
+        .sum()
+}
+
+// Width of a grapheme starting at display column `column`, where tabs extend
+// to the next tab stop
+pub fn column_width(grapheme: &str, column: usize, tab_width: usize) -> usize {
+    if grapheme == "\t" {
+        tab_width - column % tab_width
+    } else {
+        grapheme_width(grapheme)
+    }
+}
+
+// Display width of a line with its tabs expanded
+pub fn line_width(text: &str, tab_width: usize) -> usize {
+    grapheme_indices(text).fold(0, |column, (_, grapheme)| {
+        column + column_width(grapheme, column, tab_width)
+    })
+}
+
+// Index of the grapheme at display column `column`, or of the one it falls
+// inside when that's in the middle of a wide grapheme or tab
+pub fn column_to_grapheme(text: &str, column: usize, tab_width: usize) -> usize {
+    let mut width = 0;
+
+    grapheme_indices(text)
+        .take_while(|&(_, grapheme)| {
+            width += column_width(grapheme, width, tab_width);
+            width <= column
+        })
+        .count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn graphemes(text: &str) -> Vec<&str> {
+        grapheme_indices(text)
+            .map(|(_, grapheme)| grapheme)
+            .collect()
+    }
+
+    #[test]
+    fn combining_marks() {
+        assert_eq!(
+            graphemes("e\u{301}a\u{308}\u{323}"),
+            ["e\u{301}", "a\u{308}\u{323}"]
+        );
+        assert_eq!(width("e\u{301}a\u{308}\u{323}"), 2);
+    }
+
+    #[test]
+    fn zwj_sequences() {
+        let family = "👨\u{200D}👩\u{200D}👧";
+
+        assert_eq!(graphemes(&format!("{family}x")), [family, "x"]);
+        assert_eq!(grapheme_count("👍🏽👍"), 2);
+        assert_eq!(width(family), 2);
+    }
+
+    #[test]
+    fn regional_indicators() {
+        assert_eq!(graphemes("🇯🇵🇫🇷🇩"), ["🇯🇵", "🇫🇷", "🇩"]);
+        assert_eq!(width("🇯🇵🇫🇷"), 4);
+    }
+
+    #[test]
+    fn hangul() {
+        // L V T jamo, then an LV syllable with a trailing T, then an LVT one
+        assert_eq!(
+            graphemes("\u{1100}\u{1161}\u{11A8}\u{AC00}\u{11A8}\u{AC01}"),
+            ["\u{1100}\u{1161}\u{11A8}", "\u{AC00}\u{11A8}", "\u{AC01}"]
+        );
+        // A T doesn't join onto an L, nor an LVT onto a V
+        assert_eq!(grapheme_count("\u{1100}\u{11A8}"), 2);
+        assert_eq!(grapheme_count("\u{AC01}\u{1161}"), 2);
+    }
+
+    #[test]
+    fn line_breaks() {
+        assert_eq!(graphemes("a\r\n\n\u{301}"), ["a", "\r\n", "\n", "\u{301}"]);
+    }
+
+    #[test]
+    fn widths() {
+        assert_eq!(width("漢字"), 4);
+        assert_eq!(width("ｶﾅ"), 2);
+        assert_eq!(width("\u{2764}\u{FE0F}"), 2);
+        assert_eq!(width("\u{2764}"), 1);
+        assert_eq!(width("\u{301}"), 0);
+    }
+
+    #[test]
+    fn byte_offsets() {
+        let text = "a漢e\u{301}b";
+
+        assert_eq!(grapheme_to_byte(text, 0), 0);
+        assert_eq!(grapheme_to_byte(text, 2), 4);
+        assert_eq!(grapheme_to_byte(text, 3), 7);
+        assert_eq!(grapheme_to_byte(text, 10), text.len());
+
+        assert_eq!(byte_to_grapheme(text, 4), 2);
+        // Mid-grapheme offsets round up to the next grapheme
+        assert_eq!(byte_to_grapheme(text, 2), 2);
+        assert_eq!(byte_to_grapheme(text, 5), 3);
+        assert_eq!(byte_to_grapheme(text, text.len()), 4);
+    }
+
+    #[test]
+    fn columns() {
+        assert_eq!(line_width("a\tb", 4), 5);
+        assert_eq!(line_width("abcd\t", 4), 8);
+
+        // Columns inside a tab or wide grapheme land on that grapheme
+        assert_eq!(column_to_grapheme("a\tb", 0, 4), 0);
+        assert_eq!(column_to_grapheme("a\tb", 1, 4), 1);
+        assert_eq!(column_to_grapheme("a\tb", 3, 4), 1);
+        assert_eq!(column_to_grapheme("a\tb", 4, 4), 2);
+        assert_eq!(column_to_grapheme("漢字x", 1, 4), 0);
+        assert_eq!(column_to_grapheme("漢字x", 3, 4), 1);
+        assert_eq!(column_to_grapheme("漢字x", 4, 4), 2);
+        assert_eq!(column_to_grapheme("漢字x", 9, 4), 3);
+    }
+}
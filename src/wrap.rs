@@ -0,0 +1,39 @@
+use crate::unicode;
+
+// Byte offsets where each screen row of a soft wrapped line starts. Rows break
+// after whitespace when possible, and a line that exactly fills its last row
+// gets an empty row after it so the cursor has somewhere to go at its end
+pub fn wrap_line(line: &str, width: usize, tab_width: usize) -> Vec<usize> {
+    let width = width.max(1);
+    let mut starts = vec![0];
+    let mut col = 0;
+    let mut last_break = None;
+
+    for (byte, grapheme) in unicode::grapheme_indices(line) {
+        if col > 0 && col + unicode::column_width(grapheme, col, tab_width) > width {
+            // Carry the word started since the last whitespace over
+            if let Some(break_byte) = last_break.take() {
+                starts.push(break_byte);
+                col = unicode::line_width(&line[break_byte..byte], tab_width);
+            }
+
+            // Words longer than a whole row are broken anywhere
+            if col > 0 && col + unicode::column_width(grapheme, col, tab_width) > width {
+                starts.push(byte);
+                col = 0;
+            }
+        }
+
+        col += unicode::column_width(grapheme, col, tab_width);
+
+        if grapheme.chars().all(char::is_whitespace) {
+            last_break = Some(byte + grapheme.len());
+        }
+    }
+
+    if col >= width {
+        starts.push(line.len());
+    }
+
+    starts
+}